@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::Path;
+use serde::Serialize;
+use crate::protocol::team_list::TeamListPacket;
+use crate::protocol::tick::TickPacket;
+
+pub const DEFAULT_TICKS_PER_SECOND: f64 = 60.0;
+// 超过这个时间没有命令算作挂机
+pub const DEFAULT_IDLE_SECONDS: f64 = 30.0;
+
+#[derive(Debug, Default)]
+struct PlayerRecord {
+    command_ticks: Vec<i32>,
+    by_type: BTreeMap<String, u32>,
+}
+
+// 名字和队伍 玩家中途退出后保留最后一次看到的
+#[derive(Debug, Clone)]
+struct Seat {
+    nickname: String,
+    team: i32,
+}
+
+// 按命令里的玩家位置统计 和队伍列表里的slot对应
+// 同一队伍的多个玩家分开统计
+#[derive(Debug)]
+pub struct MatchAnalytics {
+    ticks_per_second: f64,
+    idle_seconds: f64,
+    first_tick: Option<i32>,
+    last_tick: i32,
+    players: BTreeMap<u8, PlayerRecord>,
+    seats: BTreeMap<u8, Seat>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IdlePeriod {
+    pub start_tick: i32,
+    pub end_tick: i32,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerReport {
+    pub slot: u8,
+    // 没收到过队伍列表时为空
+    pub nickname: String,
+    pub team: Option<i32>,
+    pub command_count: u32,
+    pub average_apm: f64,
+    pub peak_apm: f64,
+    // 每分钟一个值
+    pub apm: Vec<f64>,
+    pub by_type: BTreeMap<String, u32>,
+    pub idle_periods: Vec<IdlePeriod>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsReport {
+    pub first_tick: i32,
+    pub last_tick: i32,
+    pub duration_seconds: f64,
+    pub players: Vec<PlayerReport>,
+}
+
+impl MatchAnalytics {
+    pub fn new() -> Self {
+        Self {
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+            idle_seconds: DEFAULT_IDLE_SECONDS,
+            first_tick: None,
+            last_tick: 0,
+            players: BTreeMap::new(),
+            seats: BTreeMap::new(),
+        }
+    }
+    pub fn record_roster(&mut self, list: &TeamListPacket) {
+        for entry in &list.players {
+            if let Ok(slot) = u8::try_from(entry.slot) {
+                self.seats.insert(
+                    slot,
+                    Seat {
+                        nickname: entry.nickname.clone(),
+                        team: entry.team,
+                    },
+                );
+            }
+        }
+    }
+    pub fn record_tick(&mut self, tick: &TickPacket) {
+        if self.first_tick.is_none() {
+            self.first_tick = Some(tick.tick);
+        }
+        self.last_tick = self.last_tick.max(tick.tick);
+        for cmd in &tick.commands {
+            let record = self.players.entry(cmd.slot).or_default();
+            record.command_ticks.push(tick.tick);
+            *record.by_type.entry(cmd.action.name().to_string()).or_insert(0) += 1;
+        }
+    }
+    fn seconds(&self, ticks: i32) -> f64 {
+        ticks as f64 / self.ticks_per_second
+    }
+    pub fn report(&self) -> AnalyticsReport {
+        let first_tick = self.first_tick.unwrap_or(0);
+        let duration_seconds = self.seconds(self.last_tick - first_tick);
+        let minutes = (duration_seconds / 60.0).ceil().max(1.0) as usize;
+        let idle_ticks = (self.idle_seconds * self.ticks_per_second) as i32;
+
+        let mut players = Vec::new();
+        for (slot, record) in &self.players {
+            let mut apm = vec![0.0; minutes];
+            for tick in &record.command_ticks {
+                let minute = (self.seconds(tick - first_tick) / 60.0) as usize;
+                apm[minute.min(minutes - 1)] += 1.0;
+            }
+            // 最后一分钟不满时按实际时长折算
+            let tail = duration_seconds - (minutes - 1) as f64 * 60.0;
+            if tail > 0.0 && tail < 60.0 {
+                apm[minutes - 1] *= 60.0 / tail;
+            }
+
+            // 开局到第一条命令 命令之间 最后一条命令到结束
+            let mut idle_periods = Vec::new();
+            let mut previous = first_tick;
+            for tick in record.command_ticks.iter().copied().chain(std::iter::once(self.last_tick)) {
+                if tick - previous >= idle_ticks {
+                    idle_periods.push(IdlePeriod {
+                        start_tick: previous,
+                        end_tick: tick,
+                        seconds: self.seconds(tick - previous),
+                    });
+                }
+                previous = tick;
+            }
+
+            let command_count = record.command_ticks.len() as u32;
+            let seat = self.seats.get(slot);
+            players.push(PlayerReport {
+                slot: *slot,
+                nickname: seat.map(|s| s.nickname.clone()).unwrap_or_default(),
+                team: seat.map(|s| s.team),
+                command_count,
+                average_apm: if duration_seconds > 0.0 {
+                    command_count as f64 * 60.0 / duration_seconds
+                } else {
+                    0.0
+                },
+                peak_apm: apm.iter().cloned().fold(0.0, f64::max),
+                apm,
+                by_type: record.by_type.clone(),
+                idle_periods,
+            });
+        }
+        AnalyticsReport {
+            first_tick,
+            last_tick: self.last_tick,
+            duration_seconds,
+            players,
+        }
+    }
+}
+
+impl Default for MatchAnalytics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnalyticsReport {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    // 每个玩家每分钟一行
+    pub fn to_csv(&self) -> Result<String, std::fmt::Error> {
+        let mut csv = String::from("slot,nickname,team,minute,apm,commands_total,idle_seconds_total\n");
+        for player in &self.players {
+            let idle: f64 = player.idle_periods.iter().map(|p| p.seconds).sum();
+            let team = player.team.map(|t| t.to_string()).unwrap_or_default();
+            for (minute, apm) in player.apm.iter().enumerate() {
+                writeln!(
+                    csv,
+                    "{},{},{},{},{:.2},{},{:.2}",
+                    player.slot,
+                    csv_field(&player.nickname),
+                    team,
+                    minute,
+                    apm,
+                    player.command_count,
+                    idle
+                )?;
+            }
+        }
+        Ok(csv)
+    }
+    // 每局一对文件 name一般用开局时间
+    pub async fn write_to(&self, dir: &Path, name: &str) -> io::Result<()> {
+        let json = self.to_json().map_err(io::Error::other)?;
+        let csv = self.to_csv().map_err(io::Error::other)?;
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(dir.join(format!("{}.json", name)), json).await?;
+        tokio::fs::write(dir.join(format!("{}.csv", name)), csv).await?;
+        Ok(())
+    }
+}
+
+// 昵称里可能有逗号和引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::game_command::{CommandAction, GameCommand};
+    use crate::protocol::team_list::TeamListEntry;
+
+    fn entry(slot: i32, team: i32, nickname: &str) -> TeamListEntry {
+        TeamListEntry {
+            slot,
+            team,
+            nickname: nickname.to_string(),
+            color: 0,
+            ready: true,
+            is_host: false,
+            is_admin: false,
+            ping: 0,
+        }
+    }
+
+    fn tick(tick: i32, slots: &[u8]) -> TickPacket {
+        TickPacket {
+            tick,
+            commands: slots
+                .iter()
+                .map(|slot| GameCommand::new(*slot, vec![1], CommandAction::Move { x: 0.0, y: 0.0 }))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn counts_players_on_the_same_team_separately() {
+        let mut analytics = MatchAnalytics::default();
+        analytics.record_roster(&TeamListPacket {
+            my_slot: 0,
+            players: vec![entry(0, 0, "a"), entry(1, 0, "b,c")],
+        });
+        analytics.record_tick(&tick(0, &[0, 1]));
+        analytics.record_tick(&tick(600, &[0]));
+        // 一分钟
+        analytics.record_tick(&tick(3600, &[]));
+
+        let report = analytics.report();
+        assert_eq!(report.duration_seconds, 60.0);
+        assert_eq!(report.players.len(), 2);
+        let a = &report.players[0];
+        assert_eq!((a.slot, a.nickname.as_str(), a.team), (0, "a", Some(0)));
+        assert_eq!(a.command_count, 2);
+        assert_eq!(a.apm, vec![2.0]);
+        assert_eq!(a.by_type.get("move"), Some(&2));
+        // 600到3600没有命令
+        assert_eq!(a.idle_periods.len(), 1);
+        assert_eq!(a.idle_periods[0].seconds, 50.0);
+        assert_eq!(report.players[1].command_count, 1);
+
+        let csv = report.to_csv().unwrap();
+        assert!(csv.lines().any(|line| line == "1,\"b,c\",0,0,1.00,1,60.00"));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["players"][1]["nickname"], "b,c");
+    }
+
+    #[test]
+    fn unknown_slots_have_no_name() {
+        let mut analytics = MatchAnalytics::new();
+        analytics.record_tick(&tick(10, &[3]));
+        let report = analytics.report();
+        assert_eq!(report.players[0].nickname, "");
+        assert_eq!(report.players[0].team, None);
+    }
+}
@@ -0,0 +1,318 @@
+use std::time::Duration;
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::time::Instant;
+use crate::client::{closed_error, recv_event, FakePlayer};
+use crate::error::PacketError;
+use crate::event::ClientEvent;
+use crate::protocol::team_list::{TeamListEntry, TeamListPacket, SPECTATOR_TEAM};
+
+#[derive(Debug, Clone)]
+pub struct AutoStartPolicy {
+    // 准备好的玩家达到这个数立刻开始
+    pub ready_players: usize,
+    // 超时后只要有这么多玩家也开始
+    pub timeout: Duration,
+    pub min_players: usize,
+    pub balance_teams: bool,
+    pub team_count: i32,
+    // 开始前在聊天里倒数的秒数
+    pub countdown: u32,
+}
+impl AutoStartPolicy {
+    pub fn new() -> Self {
+        Self {
+            ready_players: 4,
+            timeout: Duration::from_secs(180),
+            min_players: 2,
+            balance_teams: true,
+            team_count: 2,
+            countdown: 5,
+        }
+    }
+    fn should_start(&self, players: &[&TeamListEntry], since: Instant) -> bool {
+        let ready = players.iter().filter(|p| p.ready).count();
+        let timed_out = since.elapsed() >= self.timeout;
+        ready >= self.ready_players || (timed_out && players.len() >= self.min_players)
+    }
+}
+impl Default for AutoStartPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 不算机器人自己和观战者
+fn humans(team_list: &TeamListPacket) -> Vec<&TeamListEntry> {
+    team_list
+        .players
+        .iter()
+        .filter(|p| p.slot != team_list.my_slot && p.team != SPECTATOR_TEAM)
+        .collect()
+}
+
+// 每次把人最多的队伍里最后进来的玩家移到人最少的队伍 直到相差不超过1
+// 机器人自己也占一个位置 算进人数但不会被移动 观战的机器人不在任何队伍里
+pub fn balance_moves(team_list: &TeamListPacket, team_count: i32) -> Vec<(i32, i32)> {
+    let mut moves = Vec::new();
+    if team_count <= 0 {
+        return moves;
+    }
+    let mut teams: Vec<Vec<i32>> = vec![Vec::new(); team_count as usize];
+    for player in &team_list.players {
+        if (0..team_count).contains(&player.team) {
+            teams[player.team as usize].push(player.slot);
+        }
+    }
+    loop {
+        let largest = (0..teams.len()).max_by_key(|t| teams[*t].len()).unwrap();
+        let smallest = (0..teams.len()).min_by_key(|t| teams[*t].len()).unwrap();
+        if teams[largest].len() <= teams[smallest].len() + 1 {
+            break;
+        }
+        // 多出来的至少两个人 里面一定有不是机器人的
+        let index = teams[largest]
+            .iter()
+            .rposition(|slot| *slot != team_list.my_slot)
+            .unwrap();
+        let slot = teams[largest].remove(index);
+        teams[smallest].push(slot);
+        moves.push((slot, smallest as i32));
+    }
+    moves
+}
+
+impl FakePlayer {
+    // 机器人当房主时自动开局 直到游戏开始
+    pub async fn run_auto_start(&self, policy: &AutoStartPolicy) -> Result<(), PacketError> {
+        let mut events = self.subscribe();
+        let mut team_list: Option<TeamListPacket> = None;
+        let mut waiting_since: Option<Instant> = None;
+        loop {
+            // 已经超时但人不够时只等名单变化
+            let deadline = waiting_since
+                .map(|since| since + policy.timeout)
+                .filter(|deadline| *deadline > Instant::now());
+            let event = tokio::select! {
+                event = recv_event(&mut events) => match event {
+                    Some(event) => Some(event),
+                    None => return Err(closed_error()),
+                },
+                _ = sleep_until(deadline) => None,
+            };
+            match event {
+                Some(ClientEvent::RosterUpdated(list)) => team_list = Some(list),
+                Some(ClientEvent::GameStarted) => return Ok(()),
+                Some(_) => continue,
+                None => {}
+            }
+            let Some(list) = &team_list else { continue };
+            let players = humans(list);
+            if players.is_empty() {
+                waiting_since = None;
+                continue;
+            }
+            let since = *waiting_since.get_or_insert_with(Instant::now);
+            if !policy.should_start(&players, since) {
+                continue;
+            }
+            let moves = if policy.balance_teams {
+                balance_moves(list, policy.team_count)
+            } else {
+                Vec::new()
+            };
+            for (slot, team) in moves {
+                // 玩家可能刚好离开了 移动失败不影响开局
+                if let Err(e) = self.move_player(slot, team).await {
+                    log::warn!("移动玩家{}到队伍{}失败:{}", slot, team, e);
+                }
+            }
+            // 聊天发不出去也照样倒数和开局
+            for remaining in (1..=policy.countdown).rev() {
+                if let Err(e) = self.send_chat(&format!("游戏将在{}秒后开始", remaining)) {
+                    log::warn!("倒数消息发送失败:{}", e);
+                }
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            // 倒数期间名单可能变了 用最新的名单再检查一次
+            loop {
+                match events.try_recv() {
+                    Ok(ClientEvent::RosterUpdated(list)) => team_list = Some(list),
+                    Ok(ClientEvent::GameStarted) => return Ok(()),
+                    Ok(_) | Err(TryRecvError::Lagged(_)) => {}
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Closed) => return Err(closed_error()),
+                }
+            }
+            let Some(list) = &team_list else { continue };
+            if !policy.should_start(&humans(list), since) {
+                if let Err(e) = self.send_chat("人数不够 取消开局") {
+                    log::warn!("取消消息发送失败:{}", e);
+                }
+                continue;
+            }
+            return self.start_game().await;
+        }
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use uuid::Uuid;
+    use super::*;
+    use crate::client::FakePlayerBuilder;
+    use crate::network::ToBytes;
+    use crate::packet::{frame, Packet};
+    use crate::protocol::chat::PACKET_CHAT;
+    use crate::protocol::register_connection::RegisterConnectionPacket;
+    use crate::protocol::start_game::PACKET_START_GAME;
+    use crate::protocol::team_list::PACKET_TEAM_LIST;
+    use crate::transport::memory;
+
+    fn player(slot: i32, team: i32, ready: bool) -> TeamListEntry {
+        TeamListEntry {
+            slot,
+            team,
+            nickname: format!("p{}", slot),
+            color: 0,
+            ready,
+            is_host: false,
+            is_admin: false,
+            ping: 0,
+        }
+    }
+
+    #[test]
+    fn balances_until_teams_differ_by_one() {
+        let list = TeamListPacket {
+            my_slot: 9,
+            players: vec![player(1, 0, true), player(2, 0, true), player(3, 0, true), player(4, 1, true)],
+        };
+        assert_eq!(balance_moves(&list, 2), vec![(3, 1)]);
+        assert_eq!(balance_moves(&list, 0), vec![]);
+    }
+
+    #[test]
+    fn balancing_counts_but_never_moves_the_bot() {
+        // 机器人在1队 两边已经平衡
+        let list = TeamListPacket {
+            my_slot: 0,
+            players: vec![player(0, 1, false), player(1, 0, true), player(2, 0, true)],
+        };
+        assert_eq!(balance_moves(&list, 2), vec![]);
+        // 机器人是0队最后一个 移动别人
+        let list = TeamListPacket {
+            my_slot: 3,
+            players: vec![player(1, 0, true), player(2, 0, true), player(3, 0, false)],
+        };
+        assert_eq!(balance_moves(&list, 2), vec![(2, 1)]);
+        // 观战的机器人不算
+        let list = TeamListPacket {
+            my_slot: 0,
+            players: vec![player(0, SPECTATOR_TEAM, false), player(1, 0, true), player(2, 0, true)],
+        };
+        assert_eq!(balance_moves(&list, 2), vec![(2, 1)]);
+    }
+
+    #[test]
+    fn humans_skip_the_bot_and_spectators() {
+        let list = TeamListPacket {
+            my_slot: 0,
+            players: vec![player(0, 0, true), player(1, SPECTATOR_TEAM, true), player(2, 1, false)],
+        };
+        let slots: Vec<i32> = humans(&list).iter().map(|p| p.slot).collect();
+        assert_eq!(slots, vec![2]);
+    }
+
+    // 机器人是0号位置的房主 在1队 两个准备好的玩家在0队
+    fn lobby_frame() -> Vec<u8> {
+        frame(PACKET_TEAM_LIST, |out| {
+            out.write_i32(0).unwrap();
+            out.write_i32(3).unwrap();
+            for (team, nickname, ready, is_host) in [(1, "bot", false, true), (0, "a", true, false), (0, "b", true, false)] {
+                out.write_bool(true).unwrap();
+                out.write_i32(team).unwrap();
+                out.write_string(nickname).unwrap();
+                out.write_i32(0).unwrap();
+                out.write_bool(ready).unwrap();
+                out.write_bool(is_host).unwrap();
+                out.write_bool(false).unwrap();
+                out.write_i32(0).unwrap();
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn counts_down_and_starts_once_enough_players_are_ready() {
+        let (connector, mut listener) = memory();
+        let (subscribed, wait_subscribed) = tokio::sync::oneshot::channel();
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap();
+            let mut register = RegisterConnectionPacket::new();
+            register.network_server_id = Uuid::new_v4().to_string();
+            stream.write_all(&register.to_bytes().unwrap()).await.unwrap();
+            // 自动开局订阅事件以后再发名单
+            wait_subscribed.await.unwrap();
+            stream.write_all(&lobby_frame()).await.unwrap();
+            // 收到开局命令前客户端发的所有聊天
+            let mut received = Vec::new();
+            loop {
+                let len = stream.read_i32().await.unwrap();
+                let packet_type = stream.read_i32().await.unwrap();
+                let mut body = vec![0u8; len as usize];
+                stream.read_exact(&mut body).await.unwrap();
+                if packet_type != PACKET_CHAT {
+                    continue;
+                }
+                let message = Packet::new(body).read_string().unwrap();
+                let start = message == ".start";
+                received.push(message);
+                if start {
+                    break;
+                }
+            }
+            stream.write_all(&frame(PACKET_START_GAME, |_| {})).await.unwrap();
+            (stream, received)
+        });
+        let player = FakePlayerBuilder::new("memory").connector(connector).connect().await.unwrap();
+        let policy = AutoStartPolicy {
+            ready_players: 2,
+            countdown: 1,
+            ..AutoStartPolicy::default()
+        };
+        // join先poll自动开局 第一次poll就订阅了事件
+        let (result, _) = tokio::join!(
+            tokio::time::timeout(Duration::from_secs(5), player.run_auto_start(&policy)),
+            async { subscribed.send(()).unwrap() },
+        );
+        result.unwrap().unwrap();
+        let (_stream, received) = server.await.unwrap();
+        let chat = received.iter().position(|m| !m.starts_with('.')).expect("没有倒数");
+        assert!(chat < received.len() - 1);
+        // 算上机器人两边已经平衡 不需要移动
+        assert!(!received.iter().any(|m| m.starts_with(".move")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn starts_when_ready_or_after_the_timeout() {
+        let policy = AutoStartPolicy {
+            ready_players: 2,
+            min_players: 2,
+            ..AutoStartPolicy::default()
+        };
+        let list = [player(1, 0, true), player(2, 1, false)];
+        let players: Vec<&TeamListEntry> = list.iter().collect();
+        let since = Instant::now();
+        assert!(!policy.should_start(&players, since));
+        tokio::time::advance(policy.timeout).await;
+        assert!(policy.should_start(&players, since));
+        assert!(!policy.should_start(&players[..1], since));
+    }
+}
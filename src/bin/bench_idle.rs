@@ -0,0 +1,147 @@
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use rwnew::network::ToBytes;
+use rwnew::packet::PacketWriter;
+use rwnew::protocol::heart::PACKET_HEART_BEAT;
+use rwnew::protocol::register_connection::RegisterConnectionPacket;
+use rwnew::swarm::{Swarm, SwarmOptions};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const MEASURE_WINDOW: Duration = Duration::from_secs(10);
+
+// 空闲机器人的内存和CPU基准 只支持Linux
+// 用法: bench_idle [机器人数量]
+// 会启动自己作为假服务器子进程 只发161和心跳 这样测到的只有客户端的开销
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("server") {
+        return serve(&args[1]).await;
+    }
+    let count: usize = args.first().map(|s| s.parse()).transpose()?.unwrap_or(1000);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?.to_string();
+    drop(listener);
+    let mut server = Command::new(std::env::current_exe()?)
+        .args(["server", &address])
+        .stdout(Stdio::null())
+        .spawn()?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let rss_before = rss_kib()?;
+    let mut options = SwarmOptions::new(&address, count);
+    options.connect_interval = Duration::from_micros(200);
+    let swarm = Swarm::start(options);
+    let deadline = Instant::now() + Duration::from_secs(60) + Duration::from_micros(200) * count as u32;
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let summary = swarm.summary();
+        let connected = *summary.get("connected").unwrap_or(&0);
+        if connected + summary.get("failed").unwrap_or(&0) + summary.get("disconnected").unwrap_or(&0) == count {
+            break;
+        }
+        if Instant::now() > deadline {
+            eprintln!("等待机器人连接超时:{:?}", summary);
+            break;
+        }
+    }
+    // 等所有机器人都至少收过一次心跳 让缓冲区和通道都分配好
+    tokio::time::sleep(HEARTBEAT_INTERVAL * 3).await;
+    let rss_after = rss_kib()?;
+    let connected = *swarm.summary().get("connected").unwrap_or(&0);
+
+    let ticks_per_sec = clock_ticks_per_sec()?;
+    let cpu_before = cpu_secs(ticks_per_sec)?;
+    let heartbeats_before = swarm.heartbeats();
+    tokio::time::sleep(MEASURE_WINDOW).await;
+    let cpu = cpu_secs(ticks_per_sec)? - cpu_before;
+    let heartbeats = swarm.heartbeats() - heartbeats_before;
+
+    println!("机器人 {} 在线 {}", count, connected);
+    println!(
+        "内存 {} KiB -> {} KiB 每个空闲机器人 {:.1} KiB",
+        rss_before,
+        rss_after,
+        (rss_after.saturating_sub(rss_before)) as f64 / connected.max(1) as f64
+    );
+    println!(
+        "{}秒内回复心跳 {} 次 CPU {:.2}秒 每次心跳 {:.1} 微秒",
+        MEASURE_WINDOW.as_secs(),
+        heartbeats,
+        cpu,
+        cpu * 1_000_000.0 / heartbeats.max(1) as f64
+    );
+    let _ = server.kill();
+    std::process::exit(0);
+}
+
+fn rss_kib() -> Result<u64, Box<dyn std::error::Error>> {
+    let status = std::fs::read_to_string("/proc/self/status")?;
+    let line = status
+        .lines()
+        .find(|l| l.starts_with("VmRSS:"))
+        .ok_or("没有VmRSS")?;
+    Ok(line.split_whitespace().nth(1).ok_or("VmRSS格式错误")?.parse()?)
+}
+
+// /proc/self/stat 里的时间单位 和sysconf(_SC_CLK_TCK)一样
+fn clock_ticks_per_sec() -> Result<f64, Box<dyn std::error::Error>> {
+    let output = Command::new("getconf").arg("CLK_TCK").output()?;
+    if !output.status.success() {
+        return Err("getconf CLK_TCK失败".into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().parse()?)
+}
+
+// 用户态加内核态
+fn cpu_secs(ticks_per_sec: f64) -> Result<f64, Box<dyn std::error::Error>> {
+    let stat = std::fs::read_to_string("/proc/self/stat")?;
+    // 进程名可能有空格 从最后一个括号后面开始数
+    let fields: Vec<&str> = stat[stat.rfind(')').ok_or("stat格式错误")? + 2..]
+        .split_whitespace()
+        .collect();
+    let utime: u64 = fields[11].parse()?;
+    let stime: u64 = fields[12].parse()?;
+    Ok((utime + stime) as f64 / ticks_per_sec)
+}
+
+async fn serve(address: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(address).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            let _ = serve_bot(stream).await;
+        });
+    }
+}
+
+async fn serve_bot(stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut reader, mut writer) = stream.into_split();
+    // 客户端发来的东西全部丢掉
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = reader.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    });
+    let mut register = RegisterConnectionPacket::new();
+    register.network_server_id = uuid::Uuid::new_v4().to_string();
+    writer.write_all(&register.to_bytes()?).await?;
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut ping: i64 = 0;
+    loop {
+        interval.tick().await;
+        ping += 1;
+        let mut out = PacketWriter::with_capacity(17);
+        let frame = out.begin_frame(PACKET_HEART_BEAT)?;
+        out.write_i64(ping)?;
+        out.write_byte(0)?;
+        out.finish_frame(frame)?;
+        writer.write_all(&out.payload).await?;
+    }
+}
@@ -0,0 +1,46 @@
+use std::process::ExitCode;
+use rwnew::scenario::{Scenario, ScenarioRunner, StepStatus};
+
+// 用法: scenario <场景文件>...
+#[tokio::main]
+async fn main() -> ExitCode {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("用法: scenario <场景文件>...");
+        return ExitCode::from(2);
+    }
+    let mut all_passed = true;
+    for path in paths {
+        let scenario = match Scenario::load(&path) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                eprintln!("{} 读取失败:{}", path, e);
+                all_passed = false;
+                continue;
+            }
+        };
+        let report = ScenarioRunner::new(scenario).run().await;
+        println!("== {}", report.name);
+        for step in &report.steps {
+            let status = match &step.status {
+                StepStatus::Passed => "PASS".to_string(),
+                StepStatus::Failed(e) => format!("FAIL {}", e),
+                StepStatus::Skipped => "SKIP".to_string(),
+            };
+            println!(
+                "  [{:>2}] {:>6}ms {} {:?}",
+                step.index + 1,
+                step.elapsed.as_millis(),
+                status,
+                step.step
+            );
+        }
+        println!("  {}", if report.passed() { "通过" } else { "失败" });
+        all_passed &= report.passed();
+    }
+    if all_passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,216 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use crate::client::{closed_error, FakePlayer, FakePlayerBuilder, CONFIRM_TIMEOUT};
+use crate::error::PacketError;
+use crate::event::{ClientEvent, DisconnectReason, Timeout};
+use crate::outbound::Sender;
+use crate::protocol::game_command::GameCommand;
+use crate::protocol::server_info::GameSettings;
+use crate::protocol::team_list::TeamListPacket;
+use crate::transport::Connector;
+
+// 阻塞的修改调用最多等这么久 比confirm_timeout多留出排在重连后面的时间
+pub const BLOCKING_CHANGE_TIMEOUT: Duration = Duration::from_secs(2 * CONFIRM_TIMEOUT.as_secs());
+
+// 给不用async的程序用 异步客户端跑在自己的运行时里 每个调用阻塞到完成为止
+// 运行时有一个后台线程 两次调用之间照常回复心跳
+// 丢掉之前先调用disconnect 不然连接会直接断开
+// 不能在tokio运行时里面创建或丢掉 block_on会panic 异步程序直接用FakePlayer
+pub struct BlockingFakePlayer {
+    player: FakePlayer,
+    change_timeout: Duration,
+    // 放在player后面 先丢掉player再关运行时
+    runtime: Runtime,
+}
+impl BlockingFakePlayer {
+    // 在tokio运行时里调用会panic
+    pub fn connect<C: Connector>(builder: FakePlayerBuilder<C>) -> Result<Self, PacketError> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("rwnew-blocking")
+            .enable_all()
+            .build()
+            .map_err(|e| PacketError::IoError(e.to_string()))?;
+        let player = runtime.block_on(builder.connect()).map_err(|e| match e.downcast::<PacketError>() {
+            Ok(e) => *e,
+            Err(e) => PacketError::IoError(e.to_string()),
+        })?;
+        Ok(Self {
+            player,
+            change_timeout: BLOCKING_CHANGE_TIMEOUT,
+            runtime,
+        })
+    }
+    // set_team之类的修改最多阻塞多久 超时返回PacketError::Timeout(Timeout::Event)
+    pub fn set_change_timeout(&mut self, timeout: Duration) {
+        self.change_timeout = timeout;
+    }
+    pub fn send_command(&self, cmd: GameCommand) -> Result<(), PacketError> {
+        self.player.send_command(cmd)
+    }
+    pub fn send_chat(&self, message: &str) -> Result<(), PacketError> {
+        self.player.send_chat(message)
+    }
+    pub fn sender(&self) -> Sender {
+        self.player.sender()
+    }
+    pub fn leave(&self) -> Result<(), PacketError> {
+        self.player.leave()
+    }
+    pub fn disconnect(&self) -> Result<(), PacketError> {
+        self.runtime.block_on(self.player.disconnect())
+    }
+    pub fn set_team(&self, team: i32) -> Result<(), PacketError> {
+        self.block_on_change(self.player.set_team(team))
+    }
+    pub fn set_color(&self, color: i32) -> Result<(), PacketError> {
+        self.block_on_change(self.player.set_color(color))
+    }
+    pub fn set_ready(&self, ready: bool) -> Result<(), PacketError> {
+        self.block_on_change(self.player.set_ready(ready))
+    }
+    pub fn change_map(&self, map_name: &str) -> Result<(), PacketError> {
+        self.block_on_change(self.player.change_map(map_name))
+    }
+    pub fn change_settings(&self, settings: GameSettings) -> Result<(), PacketError> {
+        self.block_on_change(self.player.change_settings(settings))
+    }
+    pub fn move_player(&self, slot: i32, team: i32) -> Result<(), PacketError> {
+        self.block_on_change(self.player.move_player(slot, team))
+    }
+    pub fn kick(&self, slot: i32, reason: &str) -> Result<(), PacketError> {
+        self.block_on_change(self.player.kick(slot, reason))
+    }
+    pub fn start_game(&self) -> Result<(), PacketError> {
+        self.block_on_change(self.player.start_game())
+    }
+    // 连接结束后返回None
+    pub fn next_event(&mut self) -> Option<ClientEvent> {
+        self.runtime.block_on(self.player.next_event())
+    }
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<ClientEvent>, PacketError> {
+        // 计时器要在运行时里面创建
+        let player = &mut self.player;
+        self.runtime
+            .block_on(async { tokio::time::timeout(timeout, player.next_event()).await })
+            .map_err(|_| PacketError::Timeout(Timeout::Event))
+    }
+    // 在别的线程用blocking_recv收事件
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.player.subscribe()
+    }
+    // 一直处理事件直到pick返回Some 连接先断开时返回断开的原因
+    pub fn wait_for<T>(&mut self, timeout: Duration, mut pick: impl FnMut(&ClientEvent) -> Option<T>) -> Result<T, PacketError> {
+        let player = &mut self.player;
+        self.runtime.block_on(async {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match tokio::time::timeout_at(deadline, player.next_event()).await {
+                    Ok(Some(event)) => {
+                        if let Some(value) = pick(&event) {
+                            return Ok(value);
+                        }
+                        if let ClientEvent::Disconnected { reason } = event {
+                            return Err(disconnect_error(reason));
+                        }
+                    }
+                    Ok(None) => return Err(closed_error()),
+                    Err(_) => return Err(PacketError::Timeout(Timeout::Event)),
+                }
+            }
+        })
+    }
+    pub fn wait_for_roster(&mut self, timeout: Duration) -> Result<TeamListPacket, PacketError> {
+        self.wait_for(timeout, |event| match event {
+            ClientEvent::RosterUpdated(list) => Some(list.clone()),
+            _ => None,
+        })
+    }
+    // 其他异步功能比如自动开局 用block_on在这个运行时上跑
+    pub fn player(&self) -> &FakePlayer {
+        &self.player
+    }
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+    pub fn closed(self) -> Result<(), PacketError> {
+        let Self { player, runtime, .. } = self;
+        runtime.block_on(player.closed())
+    }
+    fn block_on_change(&self, change: impl Future<Output = Result<(), PacketError>>) -> Result<(), PacketError> {
+        let timeout = self.change_timeout;
+        self.runtime
+            .block_on(async { tokio::time::timeout(timeout, change).await })
+            .map_err(|_| PacketError::Timeout(Timeout::Event))?
+    }
+}
+
+fn disconnect_error(reason: DisconnectReason) -> PacketError {
+    match reason {
+        DisconnectReason::Timeout(timeout) => PacketError::Timeout(timeout),
+        DisconnectReason::Kicked(reason) => PacketError::Kicked(reason),
+        DisconnectReason::Banned(reason) => PacketError::Banned(reason),
+        DisconnectReason::WrongPassword => PacketError::WrongPassword,
+        DisconnectReason::Error(e) => PacketError::IoError(e),
+        DisconnectReason::Left | DisconnectReason::Closed => closed_error(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use uuid::Uuid;
+    use crate::network::ToBytes;
+    use crate::protocol::register_connection::RegisterConnectionPacket;
+    use crate::transport::{memory, MemoryListener};
+
+    // 服务器在自己的线程和运行时里 发161之后只读不回
+    fn silent_server(mut listener: MemoryListener) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async {
+                let mut stream = listener.accept().await.unwrap();
+                let mut register = RegisterConnectionPacket::new();
+                register.network_server_id = Uuid::new_v4().to_string();
+                stream.write_all(&register.to_bytes().unwrap()).await.unwrap();
+                let mut buf = [0u8; 1024];
+                while let Ok(n) = stream.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                }
+            });
+        })
+    }
+
+    #[test]
+    fn changes_give_up_after_the_change_timeout() {
+        let (connector, listener) = memory();
+        let server = silent_server(listener);
+        let builder = FakePlayerBuilder::new("memory")
+            .connector(connector)
+            .confirm_timeout(Duration::from_secs(60));
+        let mut player = BlockingFakePlayer::connect(builder).unwrap();
+        player
+            .wait_for(Duration::from_secs(5), |event| matches!(event, ClientEvent::Registered).then_some(()))
+            .unwrap();
+        player.set_change_timeout(Duration::from_millis(100));
+        assert!(matches!(player.set_team(1), Err(PacketError::Timeout(Timeout::Event))));
+        assert!(matches!(player.set_ready(true), Err(PacketError::Timeout(Timeout::Event))));
+        player.disconnect().unwrap();
+        drop(player);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn connect_errors_are_packet_errors() {
+        let (connector, listener) = memory();
+        drop(listener);
+        let builder = FakePlayerBuilder::new("memory").connector(connector);
+        assert!(matches!(BlockingFakePlayer::connect(builder), Err(PacketError::IoError(_))));
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::Path;
+use bytes::Bytes;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use crate::error::PacketError;
+use crate::packet::{Packet, PacketWriter};
+
+// 本项目自己的抓包格式 不是游戏的回放文件 游戏打不开 用RecordedConnector重放
+pub const CAPTURE_HEADER: &str = "rwnewCapture";
+// 文件格式的版本 改了记录的布局就加一
+pub const CAPTURE_VERSION: i32 = 2;
+pub const GAME_VERSION: i32 = 176;
+
+const ENTRY_TICK: u8 = 0;
+const ENTRY_SENT: u8 = 1;
+const ENTRY_END: u8 = 2;
+
+// 一局里按顺序记下的内容 时间全部用游戏tick 不用墙上时间
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptureEntry {
+    // 服务器发来的整个10包 含所有玩家这一帧的命令
+    Tick(i32, Bytes),
+    // 自己发出的20包 tick是发出前收到的最后一个
+    // 服务器转发回来的话之后的Tick里还会再出现一次
+    Sent(i32, Bytes),
+    // 对局结束时最后的tick
+    End(i32),
+}
+impl CaptureEntry {
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        match self {
+            CaptureEntry::Tick(tick, frame) | CaptureEntry::Sent(tick, frame) => {
+                let kind = if matches!(self, CaptureEntry::Tick(..)) { ENTRY_TICK } else { ENTRY_SENT };
+                out.write_byte(kind)?;
+                out.write_i32(*tick)?;
+                write_frame(out, frame)
+            }
+            CaptureEntry::End(tick) => {
+                out.write_byte(ENTRY_END)?;
+                out.write_i32(*tick)
+            }
+        }
+    }
+}
+
+// 和游戏的网络包一样用Java DataOutputStream的大端格式
+// 文件头: writeUTF(CAPTURE_HEADER) 文件版本 游戏版本
+// 然后是开局用的帧 一个数量加上每帧的 长度 原始数据 依次是最后的服务器信息 队伍列表和开局包
+// 之后每条记录为 类型字节 tick 长度 原始数据 结束记录只有类型和tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub game_version: i32,
+    pub setup: Vec<Bytes>,
+    pub entries: Vec<CaptureEntry>,
+}
+impl Capture {
+    pub fn parse(data: impl Into<Bytes>) -> Result<Self, PacketError> {
+        let mut packet = Packet::new(data);
+        if packet.read_str()? != CAPTURE_HEADER {
+            return Err(PacketError::InvalidPacketType);
+        }
+        if packet.read_i32()? != CAPTURE_VERSION {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let game_version = packet.read_i32()?;
+        let setup_count = packet.read_count(4)?;
+        let mut setup = Vec::with_capacity(setup_count);
+        for _ in 0..setup_count {
+            setup.push(read_frame(&mut packet)?);
+        }
+        let mut entries = Vec::new();
+        while packet.remaining() > 0 {
+            let kind = packet.read_byte()?;
+            let tick = packet.read_i32()?;
+            let entry = match kind {
+                ENTRY_TICK => CaptureEntry::Tick(tick, read_frame(&mut packet)?),
+                ENTRY_SENT => CaptureEntry::Sent(tick, read_frame(&mut packet)?),
+                ENTRY_END => CaptureEntry::End(tick),
+                _ => return Err(PacketError::InvalidPacketType),
+            };
+            entries.push(entry);
+        }
+        Ok(Self {
+            game_version,
+            setup,
+            entries,
+        })
+    }
+}
+
+fn write_frame(out: &mut PacketWriter, frame: &[u8]) -> Result<(), PacketError> {
+    let len = i32::try_from(frame.len()).map_err(|_| PacketError::OutOfBounds)?;
+    out.write_i32(len)?;
+    out.write_bytes(frame)
+}
+
+fn read_frame(packet: &mut Packet) -> Result<Bytes, PacketError> {
+    let len = packet.read_count(1)?;
+    packet.read_bytes(len)
+}
+
+// 一局一个文件 开局时创建 对局结束时finish
+pub struct CaptureRecorder {
+    file: BufWriter<File>,
+}
+impl CaptureRecorder {
+    pub async fn create(path: &Path, setup: &[Bytes]) -> Result<Self, PacketError> {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir)
+                .await
+                .map_err(|e| PacketError::IoError(e.to_string()))?;
+        }
+        let file = File::create(path)
+            .await
+            .map_err(|e| PacketError::IoError(e.to_string()))?;
+        let mut recorder = Self {
+            file: BufWriter::new(file),
+        };
+        let mut header = PacketWriter::new();
+        header.write_string(CAPTURE_HEADER)?;
+        header.write_i32(CAPTURE_VERSION)?;
+        header.write_i32(GAME_VERSION)?;
+        header.write_i32(setup.len() as i32)?;
+        for frame in setup {
+            write_frame(&mut header, frame)?;
+        }
+        recorder.write(&header.payload).await?;
+        Ok(recorder)
+    }
+    pub async fn record(&mut self, entry: &CaptureEntry) -> Result<(), PacketError> {
+        let mut out = PacketWriter::new();
+        entry.encode(&mut out)?;
+        self.write(&out.payload).await
+    }
+    pub async fn finish(mut self) -> Result<(), PacketError> {
+        self.file
+            .flush()
+            .await
+            .map_err(|e| PacketError::IoError(e.to_string()))
+    }
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), PacketError> {
+        self.file
+            .write_all(bytes)
+            .await
+            .map_err(|e| PacketError::IoError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trip() {
+        let path = std::env::temp_dir().join(format!("rwnew-capture-{}.capture", std::process::id()));
+        let setup = vec![Bytes::from_static(b"server info"), Bytes::from_static(b"start")];
+        let entries = vec![
+            CaptureEntry::Tick(1, Bytes::from_static(b"tick 1")),
+            CaptureEntry::Sent(1, Bytes::from_static(b"command")),
+            CaptureEntry::Tick(2, Bytes::from_static(b"tick 2")),
+            CaptureEntry::End(2),
+        ];
+        let mut recorder = CaptureRecorder::create(&path, &setup).await.unwrap();
+        for entry in &entries {
+            recorder.record(entry).await.unwrap();
+        }
+        recorder.finish().await.unwrap();
+
+        let capture = Capture::parse(tokio::fs::read(&path).await.unwrap()).unwrap();
+        let _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(capture.game_version, GAME_VERSION);
+        assert_eq!(capture.setup, setup);
+        assert_eq!(capture.entries, entries);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut out = PacketWriter::new();
+        out.write_string("somethingElse").unwrap();
+        assert!(Capture::parse(out.into_vec()).is_err());
+    }
+}
@@ -0,0 +1,765 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use chrono::Local;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::connection::{Change, Connection, Output};
+use crate::error::PacketError;
+use crate::event::{ClientEvent, DisconnectReason, Timeout};
+use crate::host::HostAction;
+use crate::identity::{check_uuid, IdentityStore};
+use crate::outbound::{self, Control, Priority, Sender};
+use crate::pool::BufferPool;
+use crate::reconnect::ReconnectPolicy;
+use crate::server_commands::ServerCommands;
+use crate::protocol::chat::ChatPacket;
+use crate::protocol::game_command::GameCommand;
+use crate::capture::{CaptureEntry, CaptureRecorder};
+use crate::transport::{Connector, TcpConnector, Transport};
+use uuid::Uuid;
+
+pub use crate::connection::LobbyChange;
+
+pub const EVENT_CAPACITY: usize = 1024;
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct PlayerOptions {
+    pub nickname: String,
+    // 重连时用同一个uuid 服务器才认得是同一个玩家
+    pub client_uuid: String,
+    // 不设置时用服务器分配的颜色
+    pub color: Option<i32>,
+    pub spectator: bool,
+    pub analytics_dir: Option<PathBuf>,
+    // 抓包目录 每局一个文件
+    pub record_path: Option<PathBuf>,
+    // 为None时断线就结束
+    pub reconnect: Option<ReconnectPolicy>,
+    // 多个机器人时可以绑定不同的本地地址
+    pub local_addr: Option<IpAddr>,
+    // 事件通道预先分配这么多个位置 大量机器人时调小
+    pub event_capacity: usize,
+    pub connect_timeout: Duration,
+    // 连上到收到161
+    pub handshake_timeout: Duration,
+    // 两次108之间最长的间隔
+    pub heartbeat_timeout: Duration,
+    // 修改发出后等确认的时间
+    pub confirm_timeout: Duration,
+    // 大厅修改和房主操作用的聊天命令
+    pub commands: ServerCommands,
+}
+impl PlayerOptions {
+    pub fn new() -> Self {
+        Self {
+            nickname: "wanan".to_string(),
+            client_uuid: Uuid::new_v4().to_string(),
+            color: None,
+            spectator: false,
+            analytics_dir: None,
+            record_path: None,
+            reconnect: None,
+            local_addr: None,
+            event_capacity: EVENT_CAPACITY,
+            connect_timeout: CONNECT_TIMEOUT,
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+            heartbeat_timeout: HEARTBEAT_TIMEOUT,
+            confirm_timeout: CONFIRM_TIMEOUT,
+            commands: ServerCommands::new(),
+        }
+    }
+}
+impl Default for PlayerOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Reply = oneshot::Sender<Result<(), PacketError>>;
+
+enum Action {
+    Command(GameCommand),
+    // 需要等连接关闭时带上Reply
+    Leave(Option<Reply>),
+    Change(Change, Reply),
+}
+
+pub(crate) fn closed_error() -> PacketError {
+    PacketError::IoError("连接已经关闭".to_string())
+}
+
+// 默认直连 用connector换成代理 内存连接或抓包文件
+pub struct FakePlayerBuilder<C = TcpConnector> {
+    address: String,
+    options: PlayerOptions,
+    identities: Option<Arc<IdentityStore>>,
+    pool: Arc<BufferPool>,
+    connector: C,
+}
+impl FakePlayerBuilder {
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            options: PlayerOptions::new(),
+            identities: None,
+            pool: BufferPool::shared(),
+            connector: TcpConnector,
+        }
+    }
+}
+impl<C: Connector> FakePlayerBuilder<C> {
+    pub fn connector<D: Connector>(self, connector: D) -> FakePlayerBuilder<D> {
+        FakePlayerBuilder {
+            address: self.address,
+            options: self.options,
+            identities: self.identities,
+            pool: self.pool,
+            connector,
+        }
+    }
+    pub fn nickname(mut self, nickname: &str) -> Self {
+        self.options.nickname = nickname.to_string();
+        self
+    }
+    // 观战模式 不占玩家位置 只接收tick和命令
+    pub fn spectator(mut self, spectator: bool) -> Self {
+        self.options.spectator = spectator;
+        self
+    }
+    // 每局结束后把每个玩家的命令统计写到这个目录 文件名是match-结束时间.json和.csv
+    pub fn analytics(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.analytics_dir = Some(dir.into());
+        self
+    }
+    // 把参加的每一局的网络包录下来 每局一个文件 文件名是match-开局时间.capture
+    // 是本项目自己的格式 游戏打不开 用RecordedConnector重放
+    pub fn record(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.options.record_path = Some(dir.into());
+        self
+    }
+    // 掉线时自动重连 用同样的uuid和昵称 等待时间按policy退避
+    // 对局中异常掉线会从同步包恢复 服务器正常关闭连接算对局结束 重连后回到大厅
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.options.reconnect = Some(policy);
+        self
+    }
+    pub fn event_capacity(mut self, capacity: usize) -> Self {
+        self.options.event_capacity = capacity;
+        self
+    }
+    pub fn buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.options.connect_timeout = timeout;
+        self
+    }
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.options.handshake_timeout = timeout;
+        self
+    }
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.options.heartbeat_timeout = timeout;
+        self
+    }
+    // 队伍 颜色 准备和房主操作等这么久没有确认就返回超时
+    pub fn confirm_timeout(mut self, timeout: Duration) -> Self {
+        self.options.confirm_timeout = timeout;
+        self
+    }
+    pub fn local_addr(mut self, addr: IpAddr) -> Self {
+        self.options.local_addr = Some(addr);
+        self
+    }
+    // 从身份库取这个服务器和昵称对应的uuid和颜色
+    pub fn identity_store(mut self, store: Arc<IdentityStore>) -> Self {
+        self.identities = Some(store);
+        self
+    }
+    pub async fn connect(mut self) -> Result<FakePlayer, Box<dyn std::error::Error>> {
+        if let Some(store) = &self.identities {
+            let identity = store.get_or_create(&self.address, &self.options.nickname).await?;
+            self.options.client_uuid = identity.client_uuid;
+            if identity.color.is_some() {
+                self.options.color = identity.color;
+            }
+        }
+        // 不合法的uuid在连接前就报错 不等到161
+        check_uuid(&self.options.client_uuid)?;
+        let mut connection = Connection::new(self.options);
+        let transport = open(&self.connector, &self.address, &mut connection).await?;
+        let (outbound, writer) = outbound::channel();
+        let (control, control_rx) = mpsc::unbounded_channel();
+        tokio::spawn(writer.run(control_rx));
+        let (reader, write_failed) = attach(transport, &control, &outbound);
+
+        let (actions_tx, actions_rx) = mpsc::unbounded_channel();
+        let (events_tx, events_rx) = broadcast::channel(connection.options().event_capacity);
+        let spectator = connection.options().spectator;
+        let session = Session {
+            connector: self.connector,
+            connection,
+            reader,
+            write_failed,
+            control,
+            outbound: outbound.clone(),
+            pool: self.pool,
+            address: self.address,
+            events: events_tx,
+            reconnect_attempts: 0,
+            recorder: None,
+            actions: actions_rx,
+            replies: HashMap::new(),
+            leave_reply: None,
+        };
+        let task = tokio::spawn(session.run());
+        Ok(FakePlayer {
+            spectator,
+            outbound,
+            actions: actions_tx,
+            events: events_rx,
+            task,
+        })
+    }
+}
+
+pub struct FakePlayer {
+    spectator: bool,
+    outbound: Sender,
+    actions: mpsc::UnboundedSender<Action>,
+    events: broadcast::Receiver<ClientEvent>,
+    task: JoinHandle<Result<(), PacketError>>,
+}
+impl FakePlayer {
+    // 命令会在收到下一个tick后发出 游戏开始前的命令会一直排队到第一个tick
+    // 协议里命令不带tick 执行的tick由服务器决定
+    pub fn send_command(&self, cmd: GameCommand) -> Result<(), PacketError> {
+        if self.spectator {
+            return Err(PacketError::Spectator);
+        }
+        self.actions
+            .send(Action::Command(cmd))
+            .map_err(|_| closed_error())
+    }
+    // 连接太慢 聊天排满LOW_QUEUE_CAPACITY时返回QueueFull
+    pub fn send_chat(&self, message: &str) -> Result<(), PacketError> {
+        self.outbound.send(&ChatPacket::new(message), Priority::Low)
+    }
+    // 直接往连接里发包 不经过会话任务
+    pub fn sender(&self) -> Sender {
+        self.outbound.clone()
+    }
+    // 主动离开 不会触发重连
+    pub fn leave(&self) -> Result<(), PacketError> {
+        self.actions.send(Action::Leave(None)).map_err(|_| closed_error())
+    }
+    // 和leave一样 但会等离开包和排队的包都发出去 连接关闭后才返回
+    pub async fn disconnect(&self) -> Result<(), PacketError> {
+        let (tx, rx) = oneshot::channel();
+        self.actions
+            .send(Action::Leave(Some(tx)))
+            .map_err(|_| closed_error())?;
+        rx.await.map_err(|_| closed_error())?
+    }
+    pub async fn set_team(&self, team: i32) -> Result<(), PacketError> {
+        self.change_lobby(LobbyChange::Team(team)).await
+    }
+    pub async fn set_color(&self, color: i32) -> Result<(), PacketError> {
+        self.change_lobby(LobbyChange::Color(color)).await
+    }
+    pub async fn set_ready(&self, ready: bool) -> Result<(), PacketError> {
+        self.change_lobby(LobbyChange::Ready(ready)).await
+    }
+    async fn change_lobby(&self, change: LobbyChange) -> Result<(), PacketError> {
+        self.change(Change::Lobby(change)).await
+    }
+    pub(crate) async fn host(&self, action: HostAction) -> Result<(), PacketError> {
+        self.change(Change::Host(action)).await
+    }
+    // 最多等confirm_timeout 超时返回PacketError::Timeout(Timeout::Confirm)
+    async fn change(&self, change: Change) -> Result<(), PacketError> {
+        let (tx, rx) = oneshot::channel();
+        self.actions
+            .send(Action::Change(change, tx))
+            .map_err(|_| closed_error())?;
+        rx.await.map_err(|_| closed_error())?
+    }
+    pub async fn next_event(&mut self) -> Option<ClientEvent> {
+        recv_event(&mut self.events).await
+    }
+    // 每个订阅者都能收到全部事件 可以同时跑多个功能
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.resubscribe()
+    }
+    pub async fn closed(self) -> Result<(), PacketError> {
+        self.task
+            .await
+            .map_err(|e| PacketError::IoError(e.to_string()))?
+    }
+}
+
+// 处理太慢时漏掉的事件用Lagged告诉接收方
+pub(crate) async fn recv_event(events: &mut broadcast::Receiver<ClientEvent>) -> Option<ClientEvent> {
+    match events.recv().await {
+        Ok(event) => Some(event),
+        Err(broadcast::error::RecvError::Lagged(missed)) => Some(ClientEvent::Lagged { missed }),
+        Err(broadcast::error::RecvError::Closed) => None,
+    }
+}
+
+fn failure_reason(result: &Result<(), PacketError>) -> String {
+    match result {
+        Err(e) => e.to_string(),
+        Ok(()) => "连接已经关闭".to_string(),
+    }
+}
+
+// 连上服务器后先发预注册包 再交给读写任务
+async fn open<C: Connector>(connector: &C, address: &str, connection: &mut Connection) -> Result<C::Transport, PacketError> {
+    let options = connection.options();
+    let mut transport = tokio::time::timeout(options.connect_timeout, connector.connect(address, options))
+        .await
+        .map_err(|_| PacketError::Timeout(Timeout::Connect))?
+        .map_err(|e| PacketError::IoError(e.to_string()))?;
+    log::info!("正在连接服务器:{}", transport.info());
+
+    let hello = connection.handshake(std::time::Instant::now())?;
+    transport
+        .write_all(&hello)
+        .await
+        .map_err(|e| PacketError::IoError(e.to_string()))?;
+    Ok(transport)
+}
+
+// 读端留给会话 写端交给写任务
+fn attach<T: Transport>(
+    transport: T,
+    control: &mpsc::UnboundedSender<Control>,
+    outbound: &Sender,
+) -> (ReadHalf<T>, oneshot::Receiver<PacketError>) {
+    let (reader, writer) = tokio::io::split(transport);
+    let (failed_tx, failed_rx) = oneshot::channel();
+    let generation = outbound.next_generation();
+    let _ = control.send(Control::Attach(Box::new(writer), failed_tx, generation));
+    (reader, failed_rx)
+}
+
+// tokio驱动 协议逻辑都在Connection里 这里只管读写 计时 重连和抓包文件
+struct Session<C: Connector> {
+    connector: C,
+    connection: Connection,
+    reader: ReadHalf<C::Transport>,
+    write_failed: oneshot::Receiver<PacketError>,
+    control: mpsc::UnboundedSender<Control>,
+    outbound: Sender,
+    pool: Arc<BufferPool>,
+    address: String,
+    events: broadcast::Sender<ClientEvent>,
+    reconnect_attempts: u32,
+    recorder: Option<CaptureRecorder>,
+    actions: mpsc::UnboundedReceiver<Action>,
+    // 按Connection::change返回的编号等确认
+    replies: HashMap<u64, Reply>,
+    // disconnect()在等连接关闭
+    leave_reply: Option<Reply>,
+}
+impl<C: Connector> Session<C> {
+    async fn run(mut self) -> Result<(), PacketError> {
+        let mut result = self.run_loop().await;
+        while let Some(policy) = self.connection.options().reconnect.clone() {
+            let reason = self.disconnect_reason(&result);
+            if reason.is_fatal() {
+                // 对局中被封禁之类的 这一局回不去了
+                if reason != DisconnectReason::Left && self.connection.tick().is_some() {
+                    self.emit(ClientEvent::RejoinFailed { reason: failure_reason(&result) });
+                }
+                break;
+            }
+            if self.reconnect_attempts >= policy.max_attempts {
+                let reason = failure_reason(&result);
+                if self.connection.tick().is_some() {
+                    self.emit(ClientEvent::RejoinFailed { reason });
+                } else {
+                    self.emit(ClientEvent::ReconnectFailed { reason });
+                }
+                break;
+            }
+            self.reconnect_attempts += 1;
+            let delay = policy.delay(self.reconnect_attempts);
+            log::info!("连接断开 {:?}后进行第{}次重连", delay, self.reconnect_attempts);
+            self.emit(ClientEvent::Reconnecting {
+                attempt: self.reconnect_attempts,
+                delay,
+            });
+            if !self.wait_backoff(delay).await {
+                result = Ok(());
+                break;
+            }
+            match self.reopen().await {
+                Some(Ok(())) => result = self.run_loop().await,
+                Some(Err(e)) => {
+                    self.connection.connection_lost();
+                    result = Err(e);
+                }
+                None => {
+                    result = Ok(());
+                    break;
+                }
+            }
+        }
+        // 不再重连 还在对局中的话这一局也到此为止
+        self.connection.end_game();
+        if let Err(e) = self.flush_outputs().await {
+            log::warn!("处理输出失败:{}", e);
+        }
+        self.finish_recording().await;
+        let reason = self.disconnect_reason(&result);
+        self.emit(ClientEvent::Disconnected { reason });
+        result
+    }
+    // 重连 连接和发预注册包期间也收操作 收到离开时放弃这次重连返回None
+    async fn reopen(&mut self) -> Option<Result<(), PacketError>> {
+        let mut deferred = Vec::new();
+        let opened = {
+            // 重连才用到 放到堆上 不让每个会话一直占着这部分内存
+            let mut opening = Box::pin(open(&self.connector, &self.address, &mut self.connection));
+            loop {
+                tokio::select! {
+                    opened = &mut opening => break Some(opened),
+                    Some(action) = self.actions.recv() => {
+                        let leave = matches!(action, Action::Leave(_));
+                        deferred.push(action);
+                        if leave {
+                            break None;
+                        }
+                    }
+                }
+            }
+        };
+        let opened = match opened {
+            Some(Ok(transport)) => {
+                (self.reader, self.write_failed) = attach(transport, &self.control, &self.outbound);
+                Some(Ok(()))
+            }
+            Some(Err(e)) => Some(Err(e)),
+            None => {
+                // 可能已经发了预注册包 这个连接不要了
+                self.connection.connection_lost();
+                None
+            }
+        };
+        // 连上了的话这些操作发给新连接
+        for action in deferred {
+            let result = self.handle_action(action);
+            if let Err(e) = result.and(self.flush_outputs().await) {
+                log::warn!("处理操作失败:{}", e);
+            }
+        }
+        opened
+    }
+    // 等待重连期间照常处理操作 收到离开时返回false
+    async fn wait_backoff(&mut self, delay: Duration) -> bool {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return true,
+                Some(action) = self.actions.recv() => {
+                    let result = self.handle_action(action);
+                    if let Err(e) = result.and(self.flush_outputs().await) {
+                        log::warn!("处理操作失败:{}", e);
+                    }
+                    if self.connection.has_left() {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+    fn disconnect_reason(&self, result: &Result<(), PacketError>) -> DisconnectReason {
+        match result {
+            _ if self.connection.has_left() => DisconnectReason::Left,
+            Ok(()) => DisconnectReason::Closed,
+            Err(PacketError::Timeout(timeout)) => DisconnectReason::Timeout(*timeout),
+            Err(PacketError::Kicked(reason)) => DisconnectReason::Kicked(reason.clone()),
+            Err(PacketError::Banned(reason)) => DisconnectReason::Banned(reason.clone()),
+            Err(PacketError::WrongPassword) => DisconnectReason::WrongPassword,
+            Err(e) => DisconnectReason::Error(e.to_string()),
+        }
+    }
+    async fn run_loop(&mut self) -> Result<(), PacketError> {
+        let result = self.serve().await;
+        // 等确认的修改不会再有结果了
+        self.connection.connection_lost();
+        if let Err(e) = self.flush_outputs().await {
+            log::warn!("处理输出失败:{}", e);
+        }
+        // 旧连接的写端也不要了 之后离开时不会再往断掉的连接里写
+        let _ = self.control.send(Control::Detach { farewell: None, done: None });
+        result
+    }
+    async fn serve(&mut self) -> Result<(), PacketError> {
+        let timer = tokio::time::sleep(Duration::ZERO);
+        tokio::pin!(timer);
+        // 先读一个字节等数据 到了再从池里借缓冲区读剩下的 空闲时不占读缓冲区
+        let mut first = [0u8; 1];
+        loop {
+            let deadline = self.connection.poll_timeout().map(Instant::from_std);
+            if let Some(deadline) = deadline {
+                if timer.deadline() != deadline {
+                    timer.as_mut().reset(deadline);
+                }
+            }
+            // 为true时连接已经结束
+            let done = tokio::select! {
+                _ = &mut timer, if deadline.is_some() => {
+                    self.connection.handle_timeout(std::time::Instant::now()).map(|_| false)
+                }
+                read = self.reader.read(&mut first) => match read {
+                    Ok(0) => {
+                        log::info!("连接已经关闭");
+                        self.connection.end_game();
+                        Ok(true)
+                    }
+                    Ok(_) => self.read_more(first[0]).map(|_| false),
+                    Err(e) => {
+                        log::warn!("读取错误:{}", e);
+                        Err(PacketError::IoError(e.to_string()))
+                    }
+                },
+                failed = &mut self.write_failed => Err(failed.unwrap_or_else(|_| closed_error())),
+                Some(action) = self.actions.recv() => {
+                    self.handle_action(action).map(|_| self.connection.has_left())
+                }
+            };
+            // 出错前产生的事件和回复也要交出去
+            self.flush_outputs().await?;
+            if done? {
+                return Ok(());
+            }
+        }
+    }
+    // 已经到了的数据读出来交给Connection 只poll一次 没有数据就不等
+    // 连接关闭或出错留给下一次read发现
+    fn read_more(&mut self, first: u8) -> Result<(), PacketError> {
+        let mut buf = self.pool.take();
+        buf.push(first);
+        let mut cx = Context::from_waker(Waker::noop());
+        if let Poll::Ready(Err(e)) = pin!(self.reader.read_buf(&mut buf)).poll(&mut cx) {
+            log::warn!("读取错误:{}", e);
+        }
+        let result = self.connection.receive(&buf, std::time::Instant::now());
+        self.pool.put(buf);
+        result
+    }
+    fn handle_action(&mut self, action: Action) -> Result<(), PacketError> {
+        match action {
+            Action::Command(cmd) => self.connection.send_command(cmd)?,
+            Action::Leave(done) => {
+                self.leave_reply = done;
+                self.connection.leave()?;
+            }
+            Action::Change(change, reply) => {
+                let id = self.connection.change(change, std::time::Instant::now());
+                self.replies.insert(id, reply);
+            }
+        }
+        Ok(())
+    }
+    // 把Connection产生的输出交给写任务 事件通道和抓包文件
+    async fn flush_outputs(&mut self) -> Result<(), PacketError> {
+        while let Some(output) = self.connection.poll_output() {
+            match output {
+                Output::Transmit(frames, priority) => match self.outbound.send_bytes(frames, priority) {
+                    // 只有聊天会排满 丢掉这一条 连接照常
+                    Err(PacketError::QueueFull) => log::warn!("发送队列已满 丢弃一个低优先级的包"),
+                    result => result?,
+                },
+                Output::Event(event) => {
+                    if event == ClientEvent::Reconnected {
+                        self.reconnect_attempts = 0;
+                    }
+                    self.emit(event);
+                }
+                // 写文件的状态机很大 只有抓包和统计时才用到 放到堆上
+                Output::RecordStart(setup) => {
+                    Box::pin(self.finish_recording()).await;
+                    if let Some(dir) = &self.connection.options().record_path {
+                        let path = dir.join(Local::now().format("match-%Y%m%d-%H%M%S.capture").to_string());
+                        match Box::pin(CaptureRecorder::create(&path, &setup)).await {
+                            Ok(recorder) => self.recorder = Some(recorder),
+                            Err(e) => log::warn!("创建抓包失败:{}", e),
+                        }
+                    }
+                }
+                Output::Record(entry) => {
+                    if let Some(recorder) = &mut self.recorder {
+                        // 写不进去就不再录这一局 连接照常
+                        if let Err(e) = Box::pin(recorder.record(&entry)).await {
+                            log::warn!("写入抓包失败:{}", e);
+                            self.recorder = None;
+                        }
+                    }
+                    if let CaptureEntry::End(_) = entry {
+                        Box::pin(self.finish_recording()).await;
+                    }
+                }
+                Output::Confirmed(id, result) => {
+                    if let Some(reply) = self.replies.remove(&id) {
+                        let _ = reply.send(result);
+                    }
+                }
+                Output::Close(farewell) => {
+                    let _ = self.control.send(Control::Detach {
+                        farewell: Some(farewell),
+                        done: self.leave_reply.take(),
+                    });
+                }
+                Output::Analytics(report) => {
+                    if let Some(dir) = &self.connection.options().analytics_dir {
+                        let name = Local::now().format("match-%Y%m%d-%H%M%S").to_string();
+                        if let Err(e) = Box::pin(report.write_to(dir, &name)).await {
+                            log::warn!("写入统计失败:{}", e);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn finish_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish().await {
+                log::warn!("写入抓包失败:{}", e);
+            }
+        }
+    }
+    fn emit(&self, event: ClientEvent) {
+        // 没人接收事件时直接丢弃
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::frame;
+    use crate::protocol::start_game::PACKET_START_GAME;
+    use crate::protocol::tick::PACKET_TICK;
+    use crate::transport::memory;
+
+    #[tokio::test]
+    async fn reports_rejoin_failed_when_a_match_cannot_be_resumed() {
+        let (connector, mut listener) = memory();
+        let server = tokio::spawn(async move {
+            // 开局后不再回应 也不发161 客户端握手超时
+            let mut first = listener.accept().await.unwrap();
+            first.write_all(&frame(PACKET_START_GAME, |_| {})).await.unwrap();
+            let tick = frame(PACKET_TICK, |out| {
+                out.write_i32(5).unwrap();
+                out.write_i32(0).unwrap();
+            });
+            first.write_all(&tick).await.unwrap();
+            let second = listener.accept().await.unwrap();
+            (first, second)
+        });
+        let policy = ReconnectPolicy {
+            max_attempts: 1,
+            initial_delay: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        };
+        let mut player = FakePlayerBuilder::new("memory")
+            .connector(connector)
+            .handshake_timeout(Duration::from_millis(100))
+            .reconnect(policy)
+            .connect()
+            .await
+            .unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = player.next_event().await {
+            events.push(event);
+        }
+        let _streams = server.await.unwrap();
+        assert!(events.contains(&ClientEvent::GameStarted));
+        assert!(events.iter().any(|e| matches!(e, ClientEvent::Reconnecting { attempt: 1, .. })));
+        assert!(events.iter().any(|e| matches!(e, ClientEvent::RejoinFailed { .. })), "{:?}", events);
+        assert!(!events.iter().any(|e| matches!(e, ClientEvent::ReconnectFailed { .. })));
+        assert_eq!(
+            events.last(),
+            Some(&ClientEvent::Disconnected {
+                reason: DisconnectReason::Timeout(Timeout::Handshake)
+            })
+        );
+    }
+    // 第一次连接正常 之后的连接一直连不上
+    #[derive(Clone)]
+    struct StallAfterFirst {
+        inner: crate::transport::MemoryConnector,
+        connected: Arc<std::sync::atomic::AtomicBool>,
+    }
+    impl Connector for StallAfterFirst {
+        type Transport = tokio::io::DuplexStream;
+        fn connect<'a>(
+            &'a self,
+            address: &'a str,
+            options: &'a PlayerOptions,
+        ) -> crate::transport::ConnectFuture<'a, Self::Transport> {
+            if self.connected.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                Box::pin(std::future::pending())
+            } else {
+                self.inner.connect(address, options)
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn leave_cancels_a_reconnect_in_progress() {
+        let (inner, mut listener) = memory();
+        let connector = StallAfterFirst {
+            inner,
+            connected: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        };
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            ..ReconnectPolicy::default()
+        };
+        let mut player = FakePlayerBuilder::new("memory")
+            .connector(connector)
+            .connect_timeout(Duration::from_secs(60))
+            .reconnect(policy)
+            .connect()
+            .await
+            .unwrap();
+        // 服务器直接断开 客户端开始重连
+        drop(listener.accept().await.unwrap());
+        while !matches!(player.next_event().await, Some(ClientEvent::Reconnecting { .. })) {}
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tokio::time::timeout(Duration::from_secs(1), player.disconnect())
+            .await
+            .expect("离开要打断正在进行的连接")
+            .unwrap();
+        let mut last = None;
+        while let Some(event) = player.next_event().await {
+            last = Some(event);
+        }
+        assert_eq!(
+            last,
+            Some(ClientEvent::Disconnected {
+                reason: DisconnectReason::Left
+            })
+        );
+    }
+}
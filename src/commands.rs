@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use crate::client::{recv_event, FakePlayer};
+use crate::error::PacketError;
+use crate::event::ClientEvent;
+use crate::protocol::team_list::TeamListPacket;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Everyone,
+    Admin,
+    Owner,
+}
+
+pub struct CommandContext<'a> {
+    pub player: &'a FakePlayer,
+    pub sender: &'a str,
+    pub slot: i32,
+    pub permission: Permission,
+    pub args: Vec<String>,
+    pub team_list: Option<&'a TeamListPacket>,
+    // 机器人启动后的时间
+    pub uptime: Duration,
+}
+
+// 返回的字符串会发到聊天里 Err也一样 只是前面加上错误提示
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<String>, String>> + Send + 'a>>;
+// 可以在里面await 比如踢人或换地图 处理完之前不会处理下一条聊天
+pub type CommandHandler = Box<dyn for<'a> Fn(&'a CommandContext<'a>) -> CommandFuture<'a> + Send + Sync>;
+
+struct Command {
+    help: String,
+    permission: Permission,
+    cooldown: Duration,
+    handler: CommandHandler,
+}
+
+pub struct CommandRouter {
+    prefix: String,
+    commands: BTreeMap<String, Command>,
+    // 按昵称登记 不管服务器有没有认证都生效
+    grants: HashMap<String, Permission>,
+    last_used: HashMap<(String, String), Instant>,
+    started: Instant,
+}
+impl CommandRouter {
+    pub fn new() -> Self {
+        let mut router = Self {
+            prefix: "!".to_string(),
+            commands: BTreeMap::new(),
+            grants: HashMap::new(),
+            last_used: HashMap::new(),
+            started: Instant::now(),
+        };
+        router.register_builtin();
+        router
+    }
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+    // 昵称谁都能改 服务器没有认证过的玩家改成这个昵称也会拿到这个权限
+    // 只给能控制谁进房间的服务器 比如有密码或白名单的 登记Owner
+    pub fn grant(&mut self, nickname: &str, permission: Permission) {
+        self.grants.insert(nickname.to_string(), permission);
+    }
+    // handler返回Box::pin(async move { ... })
+    pub fn register(
+        &mut self,
+        name: &str,
+        help: &str,
+        permission: Permission,
+        cooldown: Duration,
+        handler: impl for<'a> Fn(&'a CommandContext<'a>) -> CommandFuture<'a> + Send + Sync + 'static,
+    ) {
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                help: help.to_string(),
+                permission,
+                cooldown,
+                handler: Box::new(handler),
+            },
+        );
+    }
+    // 不需要await的命令
+    pub fn register_sync(
+        &mut self,
+        name: &str,
+        help: &str,
+        permission: Permission,
+        cooldown: Duration,
+        handler: impl Fn(&CommandContext) -> Result<Option<String>, String> + Send + Sync + 'static,
+    ) {
+        self.register(name, help, permission, cooldown, move |ctx| {
+            Box::pin(std::future::ready(handler(ctx)))
+        });
+    }
+    fn register_builtin(&mut self) {
+        self.register_sync("ping", "查看自己的延迟", Permission::Everyone, Duration::from_secs(5), |ctx| {
+            let ping = ctx
+                .team_list
+                .and_then(|list| list.players.iter().find(|p| p.slot == ctx.slot))
+                .map(|p| p.ping);
+            Ok(Some(match ping {
+                Some(ping) => format!("{} 延迟 {}ms", ctx.sender, ping),
+                None => "pong".to_string(),
+            }))
+        });
+        self.register_sync("stats", "房间统计", Permission::Everyone, Duration::from_secs(10), |ctx| {
+            let (players, ready) = ctx.team_list.map_or((0, 0), |list| {
+                (list.players.len(), list.players.iter().filter(|p| p.ready).count())
+            });
+            Ok(Some(format!(
+                "玩家 {} 已准备 {} 运行 {}分钟",
+                players,
+                ready,
+                ctx.uptime.as_secs() / 60
+            )))
+        });
+    }
+    // 聊天的位置是服务器给的 那个位置上的昵称要和聊天里的一致
+    // 登记过的昵称用登记的权限 没登记的服务器认证的房主和管理员是Admin 其他人是Everyone
+    pub fn permission_of(&self, sender: &str, slot: i32, team_list: Option<&TeamListPacket>) -> Permission {
+        let Some(player) = team_list
+            .and_then(|list| list.players.iter().find(|p| p.slot == slot))
+            .filter(|p| p.nickname == sender)
+        else {
+            return Permission::Everyone;
+        };
+        match self.grants.get(sender) {
+            Some(permission) => *permission,
+            None if player.is_admin || player.is_host => Permission::Admin,
+            None => Permission::Everyone,
+        }
+    }
+    fn help(&self, permission: Permission) -> String {
+        let names: Vec<String> = self
+            .commands
+            .iter()
+            .filter(|(_, cmd)| cmd.permission <= permission)
+            .map(|(name, cmd)| format!("{}{} {}", self.prefix, name, cmd.help))
+            .collect();
+        format!("{}help 可用命令: {}", self.prefix, names.join(" | "))
+    }
+    // 不是命令或不认识的命令时返回None 不回复避免被拿来刷屏
+    pub async fn dispatch(
+        &mut self,
+        player: &FakePlayer,
+        sender: &str,
+        slot: i32,
+        message: &str,
+        team_list: Option<&TeamListPacket>,
+        now: Instant,
+    ) -> Option<String> {
+        let line = message.strip_prefix(&self.prefix)?;
+        let mut args = parse_args(line);
+        if args.is_empty() {
+            return None;
+        }
+        let name = args.remove(0).to_lowercase();
+        let permission = self.permission_of(sender, slot, team_list);
+        if name == "help" {
+            return Some(self.help(permission));
+        }
+        let command = self.commands.get(&name)?;
+        if command.permission > permission {
+            return Some(format!("{} 没有权限使用 {}{}", sender, self.prefix, name));
+        }
+        let key = (sender.to_string(), name.clone());
+        if let Some(last) = self.last_used.get(&key) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < command.cooldown {
+                let wait = (command.cooldown - elapsed).as_secs() + 1;
+                return Some(format!("{}{} 冷却中 {}秒后再试", self.prefix, name, wait));
+            }
+        }
+        self.last_used.insert(key, now);
+        let ctx = CommandContext {
+            player,
+            sender,
+            slot,
+            permission,
+            args,
+            team_list,
+            uptime: now.duration_since(self.started),
+        };
+        match (command.handler)(&ctx).await {
+            Ok(reply) => reply,
+            Err(e) => Some(format!("{}{} 出错: {}", self.prefix, name, e)),
+        }
+    }
+}
+impl Default for CommandRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 按空格分割参数 双引号里的空格不分割
+pub fn parse_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut has_arg = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+    args
+}
+
+impl FakePlayer {
+    // 在聊天里响应命令 直到连接关闭
+    pub async fn run_commands(&self, mut router: CommandRouter) -> Result<(), PacketError> {
+        let mut events = self.subscribe();
+        let mut team_list: Option<TeamListPacket> = None;
+        while let Some(event) = recv_event(&mut events).await {
+            match event {
+                ClientEvent::RosterUpdated(list) => team_list = Some(list),
+                ClientEvent::Chat(chat) => {
+                    if chat.sender.is_empty() || team_list.as_ref().map(|l| l.my_slot) == Some(chat.slot) {
+                        continue;
+                    }
+                    let reply = router
+                        .dispatch(self, &chat.sender, chat.slot, &chat.message, team_list.as_ref(), Instant::now())
+                        .await;
+                    // 聊天队列满了只丢掉这条回复 不影响后面的命令
+                    if let Some(reply) = reply {
+                        if let Err(e) = self.send_chat(&reply) {
+                            log::warn!("命令回复发送失败:{}", e);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::FakePlayerBuilder;
+    use crate::protocol::team_list::TeamListEntry;
+    use crate::transport::memory;
+
+    fn roster(nickname: &str, is_admin: bool) -> TeamListPacket {
+        TeamListPacket {
+            my_slot: 0,
+            players: vec![TeamListEntry {
+                slot: 1,
+                team: 0,
+                nickname: nickname.to_string(),
+                color: 0,
+                ready: false,
+                is_host: false,
+                is_admin,
+                ping: 42,
+            }],
+        }
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(parse_args(r#"map "Two Sides"  2"#), vec!["map", "Two Sides", "2"]);
+        assert_eq!(parse_args(r#"say """#), vec!["say", ""]);
+    }
+
+    #[test]
+    fn grants_apply_by_nickname() {
+        let mut router = CommandRouter::default();
+        router.grant("owner", Permission::Owner);
+        router.grant("muted", Permission::Everyone);
+        assert_eq!(router.permission_of("owner", 1, Some(&roster("owner", true))), Permission::Owner);
+        // 不是管理员也按登记的权限
+        assert_eq!(router.permission_of("owner", 1, Some(&roster("owner", false))), Permission::Owner);
+        assert_eq!(router.permission_of("muted", 1, Some(&roster("muted", true))), Permission::Everyone);
+        // 位置上的名字和聊天里的不一致
+        assert_eq!(router.permission_of("owner", 1, Some(&roster("other", true))), Permission::Everyone);
+        assert_eq!(router.permission_of("admin", 1, Some(&roster("admin", true))), Permission::Admin);
+        assert_eq!(router.permission_of("player", 1, Some(&roster("player", false))), Permission::Everyone);
+        assert_eq!(router.permission_of("owner", 1, None), Permission::Everyone);
+    }
+
+    #[tokio::test]
+    async fn runs_async_handlers_and_ignores_unknown_commands() {
+        let (connector, _listener) = memory();
+        let player = FakePlayerBuilder::new("memory").connector(connector).connect().await.unwrap();
+        let mut router = CommandRouter::new();
+        router.register("later", "等一下再回复", Permission::Everyone, Duration::ZERO, |ctx| {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                Ok(Some(format!("{} {}", ctx.sender, ctx.args.join(","))))
+            })
+        });
+        let list = roster("a", false);
+        let now = Instant::now();
+        let reply = router.dispatch(&player, "a", 1, "!later x y", Some(&list), now).await;
+        assert_eq!(reply.as_deref(), Some("a x,y"));
+        assert_eq!(router.dispatch(&player, "a", 1, "!nothing", Some(&list), now).await, None);
+        assert_eq!(router.dispatch(&player, "a", 1, "hello", Some(&list), now).await, None);
+        let reply = router.dispatch(&player, "a", 1, "!ping", Some(&list), now).await;
+        assert_eq!(reply.as_deref(), Some("a 延迟 42ms"));
+    }
+}
@@ -0,0 +1,820 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use bytes::{Bytes, BytesMut};
+use crate::analytics::{AnalyticsReport, MatchAnalytics};
+use crate::client::{closed_error, PlayerOptions};
+use crate::error::PacketError;
+use crate::event::{ClientEvent, Timeout};
+use crate::host::HostAction;
+use crate::network::{FromBytes, PacketModel, ToBytes};
+use crate::outbound::Priority;
+use crate::packet::{Packet, PacketWriter, FRAME_HEADER_LEN};
+use crate::protocol::chat::{ChatPacket, ChatReceivePacket, PACKET_CHAT_RECEIVE};
+use crate::protocol::disconnect::DisconnectPacket;
+use crate::protocol::game_command::GameCommand;
+use crate::protocol::heart::{HeartPacket, PACKET_HEART_BEAT};
+use crate::protocol::heart_beat::HeartBeatPacket;
+use crate::protocol::kick::{KickPacket, PACKET_KICK, PACKET_PASSWORD_ERROR};
+use crate::protocol::player_info::PlayerInfoPacket;
+use crate::protocol::preregister_connection::PreregisterConnectionPacket;
+use crate::protocol::register_connection::PACKET_PREREGISTER_CONNECTION as PACKET_REGISTER_CONNECTION;
+use crate::protocol::server_info::{ServerInfoPacket, PACKET_SERVER_INFO};
+use crate::protocol::start_game::PACKET_START_GAME;
+use crate::protocol::sync::{SyncPacket, PACKET_SYNC};
+use crate::protocol::team_list::{TeamListEntry, TeamListPacket, PACKET_TEAM_LIST, SPECTATOR_TEAM};
+use crate::protocol::tick::{TickPacket, PACKET_TICK};
+use crate::capture::CaptureEntry;
+
+// 发出修改后这么久以内收到的不符合的列表可能是服务器处理修改之前发的 不算拒绝
+pub const CONFIRM_SETTLE: Duration = Duration::from_secs(1);
+// 包头里的长度超过这个就当作坏包 不然一个假的包头就能让缓冲区涨到2GB
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+// 大厅里对自己的修改 用聊天命令发出 再用下一个队伍列表确认
+#[derive(Debug, Clone, PartialEq)]
+pub enum LobbyChange {
+    Team(i32),
+    Color(i32),
+    Ready(bool),
+}
+impl LobbyChange {
+    fn confirmed_by(&self, me: &TeamListEntry) -> bool {
+        match self {
+            LobbyChange::Team(team) => me.team == *team,
+            LobbyChange::Color(color) => me.color == *color,
+            LobbyChange::Ready(ready) => me.ready == *ready,
+        }
+    }
+}
+
+// 用来确认修改是否生效的服务器消息
+pub(crate) enum Update<'a> {
+    TeamList(&'a TeamListPacket),
+    ServerInfo(&'a ServerInfoPacket),
+    GameStarted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Lobby(LobbyChange),
+    Host(HostAction),
+}
+impl Change {
+    fn check(&self, update: &Update) -> Option<bool> {
+        match (self, update) {
+            (Change::Lobby(change), Update::TeamList(list)) => {
+                Some(list.me().is_some_and(|me| change.confirmed_by(me)))
+            }
+            (Change::Lobby(_), _) => None,
+            (Change::Host(action), update) => action.check(update),
+        }
+    }
+}
+
+struct PendingChange {
+    id: u64,
+    change: Change,
+    sent: Instant,
+}
+
+// 状态机交给调用方去做的事 按产生的顺序取出
+#[derive(Debug)]
+pub enum Output {
+    // 按优先级发给服务器 可能包含多个包
+    Transmit(Bytes, Priority),
+    Event(ClientEvent),
+    // 开局 用这些帧开始一个新的抓包文件 依次是服务器信息 队伍列表和开局包
+    RecordStart(Vec<Bytes>),
+    // 写进当前抓包的记录 End之后这一局的抓包就结束了
+    Record(CaptureEntry),
+    // change的结果 编号是change返回的
+    Confirmed(u64, Result<(), PacketError>),
+    // 先把排队的包发完 再发这个离开包 然后关闭连接
+    Close(Bytes),
+    // 一局结束时的命令统计 只在设置了analytics_dir时产生
+    Analytics(AnalyticsReport),
+}
+
+// 协议状态机 不做任何IO 也不依赖运行时
+// 收到的字节和当前时间由调用方喂进来 要发的字节和事件用poll_output取 下次需要叫醒的时间用poll_timeout取
+// 时间全部由调用方传入 可以用假时钟驱动整个会话
+pub struct Connection {
+    options: PlayerOptions,
+    // 没收完的半包 收完后释放 空闲时不占内存
+    input: BytesMut,
+    outputs: VecDeque<Output>,
+    // 第几个连接 大于1时注册成功就是重连成功
+    connections: u32,
+    // 这个连接是否收到过161
+    registered: bool,
+    rejoining: bool,
+    left: bool,
+    // 收到161或108时往后推 到期就算超时
+    deadline: Option<Instant>,
+    analytics: Option<MatchAnalytics>,
+    tick: Option<i32>,
+    pending: VecDeque<GameCommand>,
+    team_list: Option<TeamListPacket>,
+    confirms: Vec<PendingChange>,
+    // handshake之后到connection_lost之前
+    connected: bool,
+    next_change: u64,
+    // 设置了record_path时保存最后的原始106和115 开局时写进抓包
+    last_server_info: Option<Bytes>,
+    last_team_list: Option<Bytes>,
+    recording: bool,
+}
+impl Connection {
+    pub fn new(options: PlayerOptions) -> Self {
+        let analytics = options.analytics_dir.as_ref().map(|_| MatchAnalytics::new());
+        Self {
+            options,
+            input: BytesMut::new(),
+            outputs: VecDeque::new(),
+            connections: 0,
+            registered: false,
+            rejoining: false,
+            left: false,
+            deadline: None,
+            analytics,
+            tick: None,
+            pending: VecDeque::new(),
+            team_list: None,
+            confirms: Vec::new(),
+            connected: false,
+            next_change: 0,
+            last_server_info: None,
+            last_team_list: None,
+            recording: false,
+        }
+    }
+    pub fn options(&self) -> &PlayerOptions {
+        &self.options
+    }
+    // 每次连上服务器后调用 返回要最先发出去的预注册包
+    // 重连时也调用 对局中掉线的话会等同步包
+    pub fn handshake(&mut self, now: Instant) -> Result<Bytes, PacketError> {
+        self.input = BytesMut::new();
+        self.connected = true;
+        self.connections += 1;
+        self.registered = false;
+        self.rejoining = self.tick.is_some();
+        self.deadline = Some(now + self.options.handshake_timeout);
+        let mut packet = PreregisterConnectionPacket::new();
+        packet.nickname = self.options.nickname.clone();
+        let mut out = PacketWriter::new();
+        packet.encode(&mut out)?;
+        Ok(out.payload.freeze())
+    }
+    // 收到的字节可以是任意长度 不完整的包留到下次
+    // 返回错误后这个连接就不能再用了
+    pub fn receive(&mut self, data: &[u8], now: Instant) -> Result<(), PacketError> {
+        self.input.extend_from_slice(data);
+        // 每个完整的包从缓冲区切出来 和缓冲区共用内存 不再复制
+        while let Some(len) = frame_len(&self.input)? {
+            let frame = self.input.split_to(len).freeze();
+            self.handle_packet(frame, now)?;
+        }
+        if self.input.is_empty() {
+            self.input = BytesMut::new();
+        }
+        Ok(())
+    }
+    // 连接断开后调用 等确认的修改不会再有结果 直接失败 之后的修改在重新握手前都会失败
+    pub fn connection_lost(&mut self) {
+        self.connected = false;
+        self.deadline = None;
+        for pending in std::mem::take(&mut self.confirms) {
+            self.outputs.push_back(Output::Confirmed(pending.id, Err(closed_error())));
+        }
+    }
+    // 到了poll_timeout给的时间后调用 连接超时返回错误 修改超时用Output::Confirmed交出
+    pub fn handle_timeout(&mut self, now: Instant) -> Result<(), PacketError> {
+        let timeout = self.options.confirm_timeout;
+        let (expired, waiting) = std::mem::take(&mut self.confirms)
+            .into_iter()
+            .partition(|pending| now >= pending.sent + timeout);
+        self.confirms = waiting;
+        for pending in expired {
+            self.outputs
+                .push_back(Output::Confirmed(pending.id, Err(PacketError::Timeout(Timeout::Confirm))));
+        }
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                let timeout = if self.registered { Timeout::Heartbeat } else { Timeout::Handshake };
+                Err(PacketError::Timeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        let confirm = self
+            .confirms
+            .iter()
+            .map(|pending| pending.sent + self.options.confirm_timeout)
+            .min();
+        match (self.deadline, confirm) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+    pub fn poll_output(&mut self) -> Option<Output> {
+        self.outputs.pop_front()
+    }
+    // 命令会在收到下一个tick后发出 游戏开始前的命令会一直排队到第一个tick
+    // 协议里命令不带tick 执行的tick由服务器决定
+    pub fn send_command(&mut self, cmd: GameCommand) -> Result<(), PacketError> {
+        if self.options.spectator {
+            return Err(PacketError::Spectator);
+        }
+        self.pending.push_back(cmd);
+        Ok(())
+    }
+    pub fn send_chat(&mut self, message: &str) -> Result<(), PacketError> {
+        self.transmit(&ChatPacket::new(message), Priority::Low)
+    }
+    // 返回的编号用来对应之后的Output::Confirmed
+    // 每个修改最后都会有一个结果 确认 拒绝 超过confirm_timeout或连接断开
+    pub fn change(&mut self, change: Change, now: Instant) -> u64 {
+        let id = self.next_change;
+        self.next_change += 1;
+        let result = if self.connected {
+            self.send_change(&change)
+        } else {
+            Err(closed_error())
+        };
+        match result {
+            Ok(()) => self.confirms.push(PendingChange { id, change, sent: now }),
+            Err(e) => self.outputs.push_back(Output::Confirmed(id, Err(e))),
+        }
+        id
+    }
+    // 主动离开 之后不会再超时
+    pub fn leave(&mut self) -> Result<(), PacketError> {
+        self.left = true;
+        self.deadline = None;
+        let mut farewell = PacketWriter::new();
+        DisconnectPacket::new().encode(&mut farewell)?;
+        self.outputs.push_back(Output::Close(farewell.payload.freeze()));
+        Ok(())
+    }
+    pub fn has_left(&self) -> bool {
+        self.left
+    }
+    pub fn is_registered(&self) -> bool {
+        self.registered
+    }
+    pub fn tick(&self) -> Option<i32> {
+        self.tick
+    }
+    // 当前这一局到现在为止的统计
+    pub fn analytics(&self) -> Option<&MatchAnalytics> {
+        self.analytics.as_ref()
+    }
+    // 服务器正常关闭连接 或者会话不再继续时调用
+    // 对局中的话算这一局结束 交出统计 之后重连不会再等同步包
+    pub fn end_game(&mut self) {
+        let Some(tick) = self.tick.take() else {
+            return;
+        };
+        self.rejoining = false;
+        if self.recording {
+            self.recording = false;
+            self.outputs.push_back(Output::Record(CaptureEntry::End(tick)));
+        }
+        if let Some(analytics) = &mut self.analytics {
+            let mut next = MatchAnalytics::new();
+            if let Some(list) = &self.team_list {
+                next.record_roster(list);
+            }
+            let report = std::mem::replace(analytics, next).report();
+            self.outputs.push_back(Output::Analytics(report));
+        }
+        self.emit(ClientEvent::GameEnded { tick });
+    }
+
+    fn handle_packet(&mut self, frame: Bytes, now: Instant) -> Result<(), PacketError> {
+        let mut packet = Packet::new(frame);
+        let packet_type = PacketModel::from_packet(&mut packet)?;
+        packet.offset = 0;
+        let record = self.options.record_path.is_some();
+        match packet_type.model {
+            PACKET_TICK => {
+                let tick = TickPacket::from_packet(&mut packet)?;
+                self.tick = Some(tick.tick);
+                if self.recording {
+                    self.outputs.push_back(Output::Record(CaptureEntry::Tick(tick.tick, packet.payload.clone())));
+                }
+                if let Some(analytics) = &mut self.analytics {
+                    analytics.record_tick(&tick);
+                }
+                self.flush_commands()?;
+            }
+            PACKET_TEAM_LIST => {
+                let team_list = TeamListPacket::from_packet(&mut packet)?;
+                if let Some(analytics) = &mut self.analytics {
+                    analytics.record_roster(&team_list);
+                }
+                self.confirm_changes(&Update::TeamList(&team_list), now);
+                self.emit(ClientEvent::RosterUpdated(team_list.clone()));
+                self.team_list = Some(team_list);
+                if record {
+                    self.last_team_list = Some(packet.payload.clone());
+                }
+            }
+            PACKET_SERVER_INFO => {
+                let server_info = ServerInfoPacket::from_packet(&mut packet)?;
+                self.confirm_changes(&Update::ServerInfo(&server_info), now);
+                self.emit(ClientEvent::ServerInfoUpdated(server_info.settings));
+                if record {
+                    self.last_server_info = Some(packet.payload.clone());
+                }
+            }
+            PACKET_CHAT_RECEIVE => {
+                let chat = ChatReceivePacket::from_packet(&mut packet)?;
+                self.emit(ClientEvent::Chat(chat));
+            }
+            PACKET_START_GAME => {
+                // 上一局没有断开就开始了新的一局
+                self.end_game();
+                if record {
+                    let setup = [self.last_server_info.clone(), self.last_team_list.clone(), Some(packet.payload.clone())];
+                    self.outputs.push_back(Output::RecordStart(setup.into_iter().flatten().collect()));
+                    self.recording = true;
+                }
+                self.confirm_changes(&Update::GameStarted, now);
+                self.emit(ClientEvent::GameStarted);
+            }
+            PACKET_SYNC => {
+                let sync = SyncPacket::from_packet(&mut packet)?;
+                self.tick = Some(sync.tick);
+                if self.rejoining {
+                    self.rejoining = false;
+                    self.emit(ClientEvent::Rejoined { tick: sync.tick });
+                }
+            }
+            PACKET_REGISTER_CONNECTION => {
+                // 161不完整或UUID不对时连接失败 不算注册成功
+                let info = PlayerInfoPacket::new(
+                    &mut packet,
+                    &self.options.nickname,
+                    &self.options.client_uuid,
+                    self.options.color,
+                    self.options.spectator,
+                )?;
+                self.registered = true;
+                self.deadline = Some(now + self.options.heartbeat_timeout);
+                if self.connections > 1 {
+                    self.emit(ClientEvent::Reconnected);
+                }
+                self.emit(ClientEvent::Registered);
+                let mut out = PacketWriter::new();
+                info.encode(&mut out)?;
+                // 161里只能不带颜色 换到观战队伍要另发命令
+                if self.options.spectator {
+                    let command = self.options.commands.lobby(&LobbyChange::Team(SPECTATOR_TEAM));
+                    ChatPacket::new(&command).encode(&mut out)?;
+                }
+                self.transmit_frames(out, Priority::Normal);
+            }
+            PACKET_KICK => {
+                let kick = KickPacket::from_packet(&mut packet)?;
+                return Err(kick.into_error());
+            }
+            PACKET_PASSWORD_ERROR => return Err(PacketError::WrongPassword),
+            PACKET_HEART_BEAT => {
+                self.deadline = Some(now + self.options.heartbeat_timeout);
+                let heart = HeartPacket::from_packet(&mut packet)?;
+                self.transmit(&HeartBeatPacket::new(heart.ping_number), Priority::High)?;
+                self.emit(ClientEvent::Heartbeat);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    fn send_change(&mut self, change: &Change) -> Result<(), PacketError> {
+        match change {
+            Change::Lobby(change) => {
+                let command = self.options.commands.lobby(change);
+                self.transmit(&ChatPacket::new(&command), Priority::Normal)
+            }
+            Change::Host(action) => {
+                let is_host = self
+                    .team_list
+                    .as_ref()
+                    .and_then(|list| list.me())
+                    .is_some_and(|me| me.is_host || me.is_admin);
+                if !is_host {
+                    return Err(PacketError::NotHost);
+                }
+                let mut out = PacketWriter::new();
+                for command in self.options.commands.host(action) {
+                    ChatPacket::new(&command).encode(&mut out)?;
+                }
+                self.transmit_frames(out, Priority::Normal);
+                Ok(())
+            }
+        }
+    }
+    fn confirm_changes(&mut self, update: &Update, now: Instant) {
+        let mut waiting = Vec::new();
+        for pending in self.confirms.drain(..) {
+            match pending.change.check(update) {
+                Some(true) => self.outputs.push_back(Output::Confirmed(pending.id, Ok(()))),
+                // 刚发出时对不上的列表可能在路上和修改错过了 继续等
+                Some(false) if now >= pending.sent + CONFIRM_SETTLE => self.outputs.push_back(Output::Confirmed(
+                    pending.id,
+                    Err(PacketError::Rejected(format!("{:?}", pending.change))),
+                )),
+                _ => waiting.push(pending),
+            }
+        }
+        self.confirms = waiting;
+    }
+    // 这一帧的所有命令写进一个缓冲区 一次发出去
+    fn flush_commands(&mut self) -> Result<(), PacketError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut out = PacketWriter::new();
+        while let Some(cmd) = self.pending.pop_front() {
+            let start = out.payload.len();
+            cmd.encode(&mut out)?;
+            if self.recording {
+                let frame = Bytes::copy_from_slice(&out.payload[start..]);
+                let tick = self.tick.unwrap_or_default();
+                self.outputs.push_back(Output::Record(CaptureEntry::Sent(tick, frame)));
+            }
+        }
+        self.transmit_frames(out, Priority::Normal);
+        Ok(())
+    }
+    fn transmit<T: ToBytes>(&mut self, packet: &T, priority: Priority) -> Result<(), PacketError> {
+        let mut out = PacketWriter::new();
+        packet.encode(&mut out)?;
+        self.transmit_frames(out, priority);
+        Ok(())
+    }
+    fn transmit_frames(&mut self, out: PacketWriter, priority: Priority) {
+        self.outputs.push_back(Output::Transmit(out.payload.freeze(), priority));
+    }
+    fn emit(&mut self, event: ClientEvent) {
+        self.outputs.push_back(Output::Event(event));
+    }
+}
+
+// 开头完整的包的长度 包头为长度和类型各4字节 不完整时返回None
+fn frame_len(data: &[u8]) -> Result<Option<usize>, PacketError> {
+    if data.len() < FRAME_HEADER_LEN {
+        return Ok(None);
+    }
+    let total_length = i32::from_be_bytes(data[..4].try_into().unwrap());
+    let total_length = usize::try_from(total_length).map_err(|_| PacketError::OutOfBounds)?;
+    if total_length > MAX_FRAME_LEN {
+        return Err(PacketError::OutOfBounds);
+    }
+    let frame_length = FRAME_HEADER_LEN + total_length;
+    if data.len() < frame_length {
+        return Ok(None);
+    }
+    Ok(Some(frame_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::frame;
+    use crate::protocol::game_command::CommandAction;
+    use crate::protocol::preregister_connection::PACKET_PREREGISTER_CONNECTION;
+    use crate::protocol::register_connection::RegisterConnectionPacket;
+
+    fn tick_frame(tick: i32) -> Vec<u8> {
+        frame(PACKET_TICK, |out| {
+            out.write_i32(tick).unwrap();
+            out.write_i32(0).unwrap();
+        })
+    }
+
+    fn outputs(connection: &mut Connection) -> Vec<Output> {
+        std::iter::from_fn(|| connection.poll_output()).collect()
+    }
+
+    fn sync_frame(tick: i32) -> Vec<u8> {
+        frame(PACKET_SYNC, |out| out.write_i32(tick).unwrap())
+    }
+
+    fn rejoined(connection: &mut Connection) -> Option<i32> {
+        outputs(connection).into_iter().find_map(|output| match output {
+            Output::Event(ClientEvent::Rejoined { tick }) => Some(tick),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn rejoins_only_after_abnormal_disconnects() {
+        let mut connection = Connection::new(PlayerOptions::new());
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        connection.receive(&frame(PACKET_START_GAME, |_| {}), now).unwrap();
+        connection.receive(&tick_frame(5), now).unwrap();
+
+        // 掉线后直接重新握手 还在对局中
+        connection.handshake(now).unwrap();
+        connection.receive(&sync_frame(9), now).unwrap();
+        assert_eq!(rejoined(&mut connection), Some(9));
+
+        // 服务器正常关闭连接 对局结束
+        connection.end_game();
+        assert_eq!(connection.tick(), None);
+        connection.handshake(now).unwrap();
+        connection.receive(&sync_frame(12), now).unwrap();
+        assert_eq!(rejoined(&mut connection), None);
+    }
+
+    fn team_list_frame(my_team: i32) -> Vec<u8> {
+        frame(PACKET_TEAM_LIST, |out| {
+            out.write_i32(0).unwrap();
+            out.write_i32(1).unwrap();
+            out.write_bool(true).unwrap();
+            out.write_i32(my_team).unwrap();
+            out.write_string("wanan").unwrap();
+            out.write_i32(0).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_i32(0).unwrap();
+        })
+    }
+
+    fn confirmed(connection: &mut Connection) -> Vec<(u64, Result<(), PacketError>)> {
+        outputs(connection)
+            .into_iter()
+            .filter_map(|output| match output {
+                Output::Confirmed(id, result) => Some((id, result)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn confirms_lobby_changes_from_the_team_list() {
+        let mut connection = Connection::new(PlayerOptions::new());
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        let id = connection.change(Change::Lobby(LobbyChange::Team(2)), now);
+
+        // 服务器处理修改之前发出的列表 不算拒绝
+        connection.receive(&team_list_frame(0), now).unwrap();
+        assert_eq!(confirmed(&mut connection), vec![]);
+        connection.receive(&team_list_frame(2), now).unwrap();
+        assert_eq!(confirmed(&mut connection), vec![(id, Ok(()))]);
+    }
+
+    #[test]
+    fn sends_lobby_changes_as_chat_commands() {
+        let mut connection = Connection::new(PlayerOptions::new());
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        outputs(&mut connection);
+        connection.change(Change::Lobby(LobbyChange::Team(2)), now);
+        let command = ChatPacket::new(".self_team 2").to_bytes().unwrap();
+        assert!(matches!(
+            &outputs(&mut connection)[..],
+            [Output::Transmit(frames, Priority::Normal)] if frames[..] == command[..]
+        ));
+    }
+
+    #[test]
+    fn rejects_only_after_the_settle_period() {
+        let mut connection = Connection::new(PlayerOptions::new());
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        let id = connection.change(Change::Lobby(LobbyChange::Team(2)), now);
+        connection.receive(&team_list_frame(0), now + CONFIRM_SETTLE).unwrap();
+        let results = confirmed(&mut connection);
+        assert!(matches!(&results[..], [(i, Err(PacketError::Rejected(_)))] if *i == id));
+    }
+
+    #[test]
+    fn changes_time_out_without_closing_the_connection() {
+        let mut options = PlayerOptions::new();
+        let timeout = options.handshake_timeout / 2;
+        options.confirm_timeout = timeout;
+        let mut connection = Connection::new(options);
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        let id = connection.change(Change::Lobby(LobbyChange::Ready(true)), now);
+        assert_eq!(connection.poll_timeout(), Some(now + timeout));
+        assert_eq!(connection.handle_timeout(now + timeout), Ok(()));
+        assert_eq!(confirmed(&mut connection), vec![(id, Err(PacketError::Timeout(Timeout::Confirm)))]);
+    }
+
+    #[test]
+    fn changes_fail_when_the_connection_is_lost() {
+        let mut connection = Connection::new(PlayerOptions::new());
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        let first = connection.change(Change::Lobby(LobbyChange::Color(3)), now);
+        connection.connection_lost();
+        let second = connection.change(Change::Lobby(LobbyChange::Color(4)), now);
+        assert_eq!(connection.poll_timeout(), None);
+        assert_eq!(
+            confirmed(&mut connection),
+            vec![(first, Err(closed_error())), (second, Err(closed_error()))]
+        );
+    }
+
+    #[test]
+    fn records_one_capture_per_match() {
+        let mut options = PlayerOptions::new();
+        options.record_path = Some("captures".into());
+        let mut connection = Connection::new(options);
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+
+        let start = frame(PACKET_START_GAME, |_| {});
+        connection.receive(&start, now).unwrap();
+        connection.send_command(GameCommand::new(0, vec![1], CommandAction::Stop)).unwrap();
+        connection.receive(&tick_frame(5), now).unwrap();
+        connection.end_game();
+
+        let records: Vec<_> = outputs(&mut connection)
+            .into_iter()
+            .filter(|output| matches!(output, Output::RecordStart(_) | Output::Record(_)))
+            .collect();
+        assert!(matches!(&records[0], Output::RecordStart(setup) if setup[..] == [Bytes::from(start)]));
+        assert!(matches!(&records[1], Output::Record(CaptureEntry::Tick(5, frame)) if frame[..] == tick_frame(5)[..]));
+        assert!(matches!(&records[2], Output::Record(CaptureEntry::Sent(5, _))));
+        assert!(matches!(&records[3], Output::Record(CaptureEntry::End(5))));
+        assert_eq!(records.len(), 4);
+
+        // 对局结束后的tick不再录
+        connection.receive(&tick_frame(6), now).unwrap();
+        assert!(!outputs(&mut connection).iter().any(|output| matches!(output, Output::Record(_))));
+    }
+
+    fn register_frame() -> Vec<u8> {
+        let mut register = RegisterConnectionPacket::new();
+        register.network_server_id = "6f9619ff-8b86-d011-b42d-00c04fc964ff".into();
+        register.to_bytes().unwrap()
+    }
+
+    fn heart_frame(ping: i64) -> Vec<u8> {
+        frame(PACKET_HEART_BEAT, |out| {
+            out.write_i64(ping).unwrap();
+            out.write_byte(0).unwrap();
+        })
+    }
+
+    fn registered(now: Instant) -> Connection {
+        let mut connection = Connection::new(PlayerOptions::new());
+        connection.handshake(now).unwrap();
+        connection.receive(&register_frame(), now).unwrap();
+        outputs(&mut connection);
+        connection
+    }
+
+    #[test]
+    fn sends_player_info_after_the_161() {
+        let options = PlayerOptions::new();
+        let mut register = Packet::new(register_frame());
+        let info = PlayerInfoPacket::new(&mut register, &options.nickname, &options.client_uuid, None, false).unwrap();
+        let heartbeat_timeout = options.heartbeat_timeout;
+        let mut connection = Connection::new(options);
+        let now = Instant::now();
+        let hello = connection.handshake(now).unwrap();
+        assert_eq!(hello[4..8], PACKET_PREREGISTER_CONNECTION.to_be_bytes());
+        assert!(!connection.is_registered());
+
+        connection.receive(&register_frame(), now).unwrap();
+        assert!(connection.is_registered());
+        let info = info.to_bytes().unwrap();
+        assert!(matches!(
+            &outputs(&mut connection)[..],
+            [Output::Event(ClientEvent::Registered), Output::Transmit(frames, Priority::Normal)] if frames[..] == info[..]
+        ));
+        assert_eq!(connection.poll_timeout(), Some(now + heartbeat_timeout));
+    }
+
+    #[test]
+    fn spectators_join_the_spectator_team_after_the_161() {
+        let mut options = PlayerOptions::new();
+        options.spectator = true;
+        let mut register = Packet::new(register_frame());
+        let info = PlayerInfoPacket::new(&mut register, &options.nickname, &options.client_uuid, None, true).unwrap();
+        let mut expected = info.to_bytes().unwrap();
+        expected.extend(ChatPacket::new(".self_team -3").to_bytes().unwrap());
+        let mut connection = Connection::new(options);
+        let now = Instant::now();
+        connection.handshake(now).unwrap();
+        connection.receive(&register_frame(), now).unwrap();
+        assert!(matches!(
+            &outputs(&mut connection)[..],
+            [Output::Event(ClientEvent::Registered), Output::Transmit(frames, Priority::Normal)] if frames[..] == expected[..]
+        ));
+    }
+
+    #[test]
+    fn answers_heartbeats_at_high_priority() {
+        let now = Instant::now();
+        let mut connection = registered(now);
+        let later = now + Duration::from_secs(3);
+        connection.receive(&heart_frame(42), later).unwrap();
+        let reply = HeartBeatPacket::new(42).to_bytes().unwrap();
+        assert!(matches!(
+            &outputs(&mut connection)[..],
+            [Output::Transmit(frames, Priority::High), Output::Event(ClientEvent::Heartbeat)] if frames[..] == reply[..]
+        ));
+        // 每个心跳都把超时往后推
+        assert_eq!(connection.poll_timeout(), Some(later + connection.options().heartbeat_timeout));
+    }
+
+    #[test]
+    fn times_out_waiting_for_the_161_or_heartbeats() {
+        let now = Instant::now();
+        let mut connection = Connection::new(PlayerOptions::new());
+        connection.handshake(now).unwrap();
+        let handshake_timeout = connection.options().handshake_timeout;
+        assert_eq!(connection.poll_timeout(), Some(now + handshake_timeout));
+        assert_eq!(connection.handle_timeout(now + handshake_timeout - Duration::from_millis(1)), Ok(()));
+        assert_eq!(
+            connection.handle_timeout(now + handshake_timeout),
+            Err(PacketError::Timeout(Timeout::Handshake))
+        );
+
+        let mut connection = registered(now);
+        let heartbeat_timeout = connection.options().heartbeat_timeout;
+        assert_eq!(
+            connection.handle_timeout(now + heartbeat_timeout),
+            Err(PacketError::Timeout(Timeout::Heartbeat))
+        );
+    }
+
+    #[test]
+    fn reassembles_frames_split_across_reads() {
+        let mut data = register_frame();
+        data.extend(heart_frame(7));
+        data.extend(heart_frame(8));
+        let now = Instant::now();
+
+        let mut connection = Connection::new(PlayerOptions::new());
+        connection.handshake(now).unwrap();
+        for byte in &data {
+            connection.receive(std::slice::from_ref(byte), now).unwrap();
+        }
+        let one_by_one = outputs(&mut connection);
+
+        // 在包头中间切开 第二段里有两个半包
+        let mut connection = Connection::new(PlayerOptions::new());
+        connection.handshake(now).unwrap();
+        connection.receive(&data[..3], now).unwrap();
+        assert!(outputs(&mut connection).is_empty());
+        connection.receive(&data[3..data.len() - 5], now).unwrap();
+        connection.receive(&data[data.len() - 5..], now).unwrap();
+        let in_chunks = outputs(&mut connection);
+
+        for outputs in [one_by_one, in_chunks] {
+            let heartbeats = outputs
+                .iter()
+                .filter(|output| matches!(output, Output::Event(ClientEvent::Heartbeat)))
+                .count();
+            assert!(matches!(outputs[0], Output::Event(ClientEvent::Registered)));
+            assert_eq!(heartbeats, 2);
+            assert_eq!(outputs.len(), 6);
+        }
+    }
+
+    #[test]
+    fn rejects_a_malformed_161() {
+        let now = Instant::now();
+        let truncated = frame(PACKET_REGISTER_CONNECTION, |out| {
+            out.write_string("com.corrodinggames.rts").unwrap();
+            out.write_i32(2).unwrap();
+        });
+        let mut bad_uuid = RegisterConnectionPacket::new();
+        bad_uuid.network_server_id = "not a uuid".into();
+        let bad_uuid = bad_uuid.to_bytes().unwrap();
+        for (register, expected) in [
+            (truncated, PacketError::OutOfBounds),
+            (bad_uuid, PacketError::InvalidUuid(String::new())),
+        ] {
+            let mut connection = Connection::new(PlayerOptions::new());
+            connection.handshake(now).unwrap();
+            let error = connection.receive(&register, now).unwrap_err();
+            assert_eq!(std::mem::discriminant(&error), std::mem::discriminant(&expected), "{}", error);
+            assert!(!connection.is_registered());
+        }
+    }
+
+    #[test]
+    fn rejects_frames_with_a_bad_length() {
+        let now = Instant::now();
+        for len in [-1, MAX_FRAME_LEN as i32 + 1] {
+            let mut connection = Connection::new(PlayerOptions::new());
+            connection.handshake(now).unwrap();
+            let mut header = len.to_be_bytes().to_vec();
+            header.extend(PACKET_CHAT_RECEIVE.to_be_bytes());
+            assert_eq!(connection.receive(&header, now), Err(PacketError::OutOfBounds));
+        }
+    }
+}
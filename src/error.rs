@@ -1,12 +1,25 @@
 use std::fmt;
+use std::str::Utf8Error;
 use std::string::FromUtf8Error;
+use crate::event::Timeout;
 
 #[derive(Debug, PartialEq)]
 pub enum PacketError {
     OutOfBounds,
     InvalidPacketType,
-    Utf8Error(FromUtf8Error),
+    Utf8Error(Utf8Error),
     IoError(String),
+    Spectator,
+    Rejected(String),
+    NotHost,
+    Timeout(Timeout),
+    Kicked(String),
+    Banned(String),
+    WrongPassword,
+    // 低优先级队列满了 这个包没有发出去
+    QueueFull,
+    // 客户端或服务器的UUID格式不对
+    InvalidUuid(String),
 }
 
 impl fmt::Display for PacketError {
@@ -16,12 +29,29 @@ impl fmt::Display for PacketError {
             PacketError::InvalidPacketType => write!(f, "Invalid packet type"),
             PacketError::Utf8Error(e) => write!(f, "UTF-8 error: {}", e),
             PacketError::IoError(e) => write!(f, "IO error: {}", e),
+            PacketError::Spectator => write!(f, "Spectators cannot send game commands"),
+            PacketError::Rejected(e) => write!(f, "Rejected by server: {}", e),
+            PacketError::NotHost => write!(f, "Not the room host or an admin"),
+            PacketError::Timeout(t) => write!(f, "Timed out waiting for {}", t),
+            PacketError::Kicked(e) => write!(f, "Kicked by server: {}", e),
+            PacketError::Banned(e) => write!(f, "Banned by server: {}", e),
+            PacketError::WrongPassword => write!(f, "Wrong server password"),
+            PacketError::QueueFull => write!(f, "Outgoing queue is full"),
+            PacketError::InvalidUuid(e) => write!(f, "Invalid UUID: {}", e),
         }
     }
 }
 
+impl std::error::Error for PacketError {}
+
+impl From<Utf8Error> for PacketError {
+    fn from(err: Utf8Error) -> Self {
+        PacketError::Utf8Error(err)
+    }
+}
+
 impl From<FromUtf8Error> for PacketError {
     fn from(err: FromUtf8Error) -> Self {
-        PacketError::Utf8Error(err)
+        PacketError::Utf8Error(err.utf8_error())
     }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::time::Duration;
+use crate::protocol::chat::ChatReceivePacket;
+use crate::protocol::server_info::GameSettings;
+use crate::protocol::team_list::TeamListPacket;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    // 调用了leave
+    Left,
+    // 服务器关闭了连接
+    Closed,
+    Timeout(Timeout),
+    Kicked(String),
+    Banned(String),
+    WrongPassword,
+    Error(String),
+}
+impl DisconnectReason {
+    // 这些情况重连也没用
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            DisconnectReason::Left | DisconnectReason::Banned(_) | DisconnectReason::WrongPassword
+        )
+    }
+}
+
+// 哪一步等太久了
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timeout {
+    Connect,
+    // 连上以后一直没收到161
+    Handshake,
+    // 太久没收到108 一般是半开的连接
+    Heartbeat,
+    // 同步接口等事件等太久
+    Event,
+    // 修改发出后一直没有收到确认
+    Confirm,
+}
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timeout::Connect => write!(f, "connect"),
+            Timeout::Handshake => write!(f, "handshake"),
+            Timeout::Heartbeat => write!(f, "heartbeat"),
+            Timeout::Event => write!(f, "event"),
+            Timeout::Confirm => write!(f, "confirmation"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    // 收到161 开始注册
+    Registered,
+    // 收到108并已回复
+    Heartbeat,
+    // 断线后等delay再进行第attempt次重连
+    Reconnecting { attempt: u32, delay: Duration },
+    // 重连后重新收到161
+    Reconnected,
+    // 不在对局中 超过最大次数 不再重连
+    ReconnectFailed { reason: String },
+    // 对局中重连后 从同步包恢复到这个tick
+    Rejoined { tick: i32 },
+    // 对局中掉线后没能回去 重连次数用完或者遇到封禁之类不能重连的原因
+    RejoinFailed { reason: String },
+    // 每次收到队伍列表
+    RosterUpdated(TeamListPacket),
+    ServerInfoUpdated(GameSettings),
+    GameStarted,
+    // 对局中服务器正常关闭连接 或会话结束 tick是收到的最后一个
+    GameEnded { tick: i32 },
+    Chat(ChatReceivePacket),
+    // 接收方处理太慢 事件通道满了 中间missed个事件已经丢掉
+    // 不是服务器发来的 只由next_event之类的接收方法产生
+    Lagged { missed: u64 },
+    Disconnected { reason: DisconnectReason },
+}
@@ -0,0 +1,131 @@
+use crate::client::FakePlayer;
+use crate::connection::Update;
+use crate::error::PacketError;
+use crate::protocol::server_info::GameSettings;
+
+// 机器人是房主或管理员时才能用 用聊天命令发出 再用下一个服务器信息或队伍列表确认
+// 和大厅修改一样最多等confirm_timeout 超时返回PacketError::Timeout(Timeout::Confirm)
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostAction {
+    ChangeMap(String),
+    ChangeSettings(GameSettings),
+    MovePlayer { slot: i32, team: i32 },
+    Kick { slot: i32, reason: String },
+    StartGame,
+}
+impl HostAction {
+    // None 表示这个更新和本操作无关 继续等
+    pub(crate) fn check(&self, update: &Update) -> Option<bool> {
+        match (self, update) {
+            (HostAction::ChangeMap(map_name), Update::ServerInfo(info)) => {
+                Some(info.settings.map_name == *map_name)
+            }
+            (HostAction::ChangeSettings(settings), Update::ServerInfo(info)) => {
+                Some(info.settings == *settings)
+            }
+            (HostAction::MovePlayer { slot, team }, Update::TeamList(list)) => {
+                Some(list.players.iter().any(|p| p.slot == *slot && p.team == *team))
+            }
+            (HostAction::Kick { slot, .. }, Update::TeamList(list)) => {
+                Some(!list.players.iter().any(|p| p.slot == *slot))
+            }
+            (HostAction::StartGame, Update::GameStarted) => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl FakePlayer {
+    pub async fn change_map(&self, map_name: &str) -> Result<(), PacketError> {
+        self.host(HostAction::ChangeMap(map_name.to_string())).await
+    }
+    pub async fn change_settings(&self, settings: GameSettings) -> Result<(), PacketError> {
+        self.host(HostAction::ChangeSettings(settings)).await
+    }
+    pub async fn move_player(&self, slot: i32, team: i32) -> Result<(), PacketError> {
+        self.host(HostAction::MovePlayer { slot, team }).await
+    }
+    pub async fn kick(&self, slot: i32, reason: &str) -> Result<(), PacketError> {
+        self.host(HostAction::Kick {
+            slot,
+            reason: reason.to_string(),
+        })
+        .await
+    }
+    pub async fn start_game(&self) -> Result<(), PacketError> {
+        self.host(HostAction::StartGame).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::*;
+    use crate::client::PlayerOptions;
+    use crate::connection::{Change, Connection, Output};
+    use crate::event::Timeout;
+    use crate::packet::frame;
+    use crate::protocol::start_game::PACKET_START_GAME;
+    use crate::protocol::team_list::PACKET_TEAM_LIST;
+
+    // 只有自己一个人 在0号位置
+    fn host_list() -> Vec<u8> {
+        frame(PACKET_TEAM_LIST, |out| {
+            out.write_i32(0).unwrap();
+            out.write_i32(1).unwrap();
+            out.write_bool(true).unwrap();
+            out.write_i32(0).unwrap();
+            out.write_string("wanan").unwrap();
+            out.write_i32(0).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_bool(true).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_i32(0).unwrap();
+        })
+    }
+
+    fn host(confirm_timeout: std::time::Duration, now: Instant) -> Connection {
+        let mut options = PlayerOptions::new();
+        options.confirm_timeout = confirm_timeout;
+        let mut connection = Connection::new(options);
+        connection.handshake(now).unwrap();
+        connection.receive(&host_list(), now).unwrap();
+        while connection.poll_output().is_some() {}
+        connection
+    }
+
+    fn confirmed(connection: &mut Connection) -> Option<(u64, Result<(), PacketError>)> {
+        std::iter::from_fn(|| connection.poll_output()).find_map(|output| match output {
+            Output::Confirmed(id, result) => Some((id, result)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn start_game_is_confirmed_by_the_start_packet() {
+        let now = Instant::now();
+        let mut connection = host(std::time::Duration::from_secs(1), now);
+        let id = connection.change(Change::Host(HostAction::StartGame), now);
+        connection.receive(&frame(PACKET_START_GAME, |_| {}), now).unwrap();
+        assert_eq!(confirmed(&mut connection), Some((id, Ok(()))));
+    }
+
+    #[test]
+    fn host_actions_time_out() {
+        let now = Instant::now();
+        let timeout = std::time::Duration::from_secs(1);
+        let mut connection = host(timeout, now);
+        let id = connection.change(Change::Host(HostAction::MovePlayer { slot: 3, team: 1 }), now);
+        connection.handle_timeout(now + timeout).unwrap();
+        assert_eq!(confirmed(&mut connection), Some((id, Err(PacketError::Timeout(Timeout::Confirm)))));
+    }
+
+    #[test]
+    fn only_hosts_can_send_host_actions() {
+        let now = Instant::now();
+        let mut connection = Connection::new(PlayerOptions::new());
+        connection.handshake(now).unwrap();
+        let id = connection.change(Change::Host(HostAction::StartGame), now);
+        assert_eq!(confirmed(&mut connection), Some((id, Err(PacketError::NotHost))));
+    }
+}
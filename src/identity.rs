@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub nickname: String,
+    pub client_uuid: String,
+    pub color: Option<i32>,
+}
+impl Identity {
+    // uuid不对的话握手时才会失败 存进来之前先检查
+    pub fn validate(&self) -> std::io::Result<()> {
+        check_uuid(&self.client_uuid)
+    }
+}
+
+pub fn check_uuid(uuid: &str) -> std::io::Result<()> {
+    Uuid::parse_str(uuid)
+        .map(|_| ())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("uuid格式错误{}:{}", uuid, e)))
+}
+
+// 按 服务器地址+昵称 保存身份 每次启动都用同一个uuid
+// 所有客户端共用一个store 改动后立刻写回文件
+// 写文件时一直拿着锁 并发的修改按顺序落盘
+pub struct IdentityStore {
+    path: PathBuf,
+    identities: Mutex<BTreeMap<String, Identity>>,
+}
+impl IdentityStore {
+    pub async fn open(path: impl AsRef<Path>) -> std::io::Result<Arc<Self>> {
+        let path = path.as_ref().to_path_buf();
+        let identities: BTreeMap<String, Identity> = match tokio::fs::read_to_string(&path).await {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        for identity in identities.values() {
+            identity.validate()?;
+        }
+        Ok(Arc::new(Self {
+            path,
+            identities: Mutex::new(identities),
+        }))
+    }
+    fn key(server: &str, nickname: &str) -> String {
+        format!("{}/{}", server, nickname)
+    }
+    pub async fn get(&self, server: &str, nickname: &str) -> Option<Identity> {
+        let identities = self.identities.lock().await;
+        identities.get(&Self::key(server, nickname)).cloned()
+    }
+    pub async fn get_or_create(&self, server: &str, nickname: &str) -> std::io::Result<Identity> {
+        let mut identities = self.identities.lock().await;
+        let key = Self::key(server, nickname);
+        if let Some(identity) = identities.get(&key) {
+            return Ok(identity.clone());
+        }
+        let identity = Identity {
+            nickname: nickname.to_string(),
+            client_uuid: Uuid::new_v4().to_string(),
+            color: None,
+        };
+        identities.insert(key, identity.clone());
+        self.save(&identities).await?;
+        Ok(identity)
+    }
+    // 单独给某个服务器设置身份 比如管理员白名单里登记过的uuid
+    pub async fn set(&self, server: &str, identity: Identity) -> std::io::Result<()> {
+        identity.validate()?;
+        let mut identities = self.identities.lock().await;
+        identities.insert(Self::key(server, &identity.nickname), identity);
+        self.save(&identities).await
+    }
+    async fn save(&self, identities: &BTreeMap<String, Identity>) -> std::io::Result<()> {
+        let text = serde_json::to_string_pretty(identities)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        tokio::fs::write(&self.path, text).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_same_uuid_after_reopening() {
+        let path = std::env::temp_dir().join(format!("rwnew-identities-{}.json", std::process::id()));
+        let store = IdentityStore::open(&path).await.unwrap();
+        let first = store.get_or_create("127.0.0.1:5123", "bot").await.unwrap();
+        let other = store.get_or_create("127.0.0.1:5124", "bot").await.unwrap();
+        assert_ne!(first.client_uuid, other.client_uuid);
+
+        let reopened = IdentityStore::open(&path).await.unwrap();
+        let _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(reopened.get("127.0.0.1:5123", "bot").await, Some(first));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_uuids() {
+        let path = std::env::temp_dir().join(format!("rwnew-identities-bad-{}.json", std::process::id()));
+        let store = IdentityStore::open(&path).await.unwrap();
+        let bad = Identity {
+            nickname: "bot".to_string(),
+            client_uuid: "not a uuid".to_string(),
+            color: None,
+        };
+        let error = store.set("127.0.0.1:5123", bad.clone()).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(store.get("127.0.0.1:5123", "bot").await, None);
+
+        let file = BTreeMap::from([("127.0.0.1:5123/bot".to_string(), bad)]);
+        tokio::fs::write(&path, serde_json::to_string(&file).unwrap()).await.unwrap();
+        let opened = IdentityStore::open(&path).await;
+        let _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(opened.err().map(|e| e.kind()), Some(std::io::ErrorKind::InvalidData));
+    }
+}
@@ -0,0 +1,26 @@
+pub mod network;
+pub mod protocol;
+pub mod packet;
+pub mod error;
+pub mod packet_utils;
+pub mod client;
+pub mod blocking;
+pub mod connection;
+pub mod analytics;
+pub mod capture;
+pub mod event;
+pub mod identity;
+pub mod host;
+pub mod server_commands;
+pub mod autostart;
+pub mod moderation;
+pub mod commands;
+pub mod scripting;
+pub mod scenario;
+pub mod swarm;
+pub mod loadtest;
+pub mod pool;
+pub mod outbound;
+pub mod reconnect;
+pub mod shutdown;
+pub mod transport;
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use serde::Serialize;
+use crate::event::{DisconnectReason, Timeout};
+
+// 直方图每个桶的上限 毫秒 最后一个桶放更大的值
+pub const HISTOGRAM_BOUNDS_MS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+fn bucket_of(ms: f64) -> usize {
+    HISTOGRAM_BOUNDS_MS
+        .iter()
+        .position(|upper| ms <= *upper as f64)
+        .unwrap_or(HISTOGRAM_BOUNDS_MS.len())
+}
+
+// 样本太多不能全留着时用 只记每个桶的数量 占用的内存是固定的
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: [usize; HISTOGRAM_BOUNDS_MS.len() + 1],
+    count: usize,
+    sum_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+}
+impl Histogram {
+    pub fn record(&mut self, value: Duration) {
+        let ms = value.as_secs_f64() * 1000.0;
+        self.counts[bucket_of(ms)] += 1;
+        if self.count == 0 || ms < self.min_ms {
+            self.min_ms = ms;
+        }
+        self.max_ms = self.max_ms.max(ms);
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+// 单个机器人的计时
+#[derive(Debug, Clone, Default)]
+pub struct BotTiming {
+    // 最近一次连接到收到161
+    pub handshake: Option<Duration>,
+    // 最近一次连接161到第一次心跳
+    pub first_heartbeat: Option<Duration>,
+    // 已经回复的心跳次数
+    pub heartbeats: u64,
+    // 失败的种类 见failure_kind 具体原因在机器人状态里
+    pub failure: Option<&'static str>,
+}
+
+// 报告里按种类统计失败 不按原始的错误文字
+pub fn failure_kind(reason: &DisconnectReason) -> Option<&'static str> {
+    match reason {
+        DisconnectReason::Left => None,
+        DisconnectReason::Closed => Some("closed"),
+        DisconnectReason::Timeout(Timeout::Connect) => Some("timeout_connect"),
+        DisconnectReason::Timeout(Timeout::Handshake) => Some("timeout_handshake"),
+        DisconnectReason::Timeout(Timeout::Heartbeat) => Some("timeout_heartbeat"),
+        DisconnectReason::Timeout(_) => Some("timeout"),
+        DisconnectReason::Kicked(_) => Some("kicked"),
+        DisconnectReason::Banned(_) => Some("banned"),
+        DisconnectReason::WrongPassword => Some("wrong_password"),
+        DisconnectReason::Error(_) => Some("error"),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    // None 表示超过最大的上限
+    pub upper_ms: Option<u64>,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub histogram: Vec<Bucket>,
+}
+impl Distribution {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        let mut ms: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        ms.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f64| -> f64 {
+            if ms.is_empty() {
+                return 0.0;
+            }
+            let rank = ((p / 100.0) * (ms.len() - 1) as f64).round() as usize;
+            ms[rank]
+        };
+        let mut histogram: Vec<Bucket> = HISTOGRAM_BOUNDS_MS
+            .iter()
+            .map(|upper| Bucket {
+                upper_ms: Some(*upper),
+                count: 0,
+            })
+            .chain(std::iter::once(Bucket {
+                upper_ms: None,
+                count: 0,
+            }))
+            .collect();
+        for value in &ms {
+            histogram[bucket_of(*value)].count += 1;
+        }
+        Self {
+            count: ms.len(),
+            min_ms: ms.first().copied().unwrap_or(0.0),
+            mean_ms: if ms.is_empty() {
+                0.0
+            } else {
+                ms.iter().sum::<f64>() / ms.len() as f64
+            },
+            p50_ms: percentile(50.0),
+            p90_ms: percentile(90.0),
+            p99_ms: percentile(99.0),
+            max_ms: ms.last().copied().unwrap_or(0.0),
+            histogram,
+        }
+    }
+    // 百分位只能精确到桶 取所在桶的上限 最后一个桶取最大值
+    pub fn from_histogram(histogram: &Histogram) -> Self {
+        let percentile = |p: f64| -> f64 {
+            if histogram.count == 0 {
+                return 0.0;
+            }
+            let rank = ((p / 100.0) * (histogram.count - 1) as f64).round() as usize;
+            let mut seen = 0;
+            for (index, count) in histogram.counts.iter().enumerate() {
+                seen += count;
+                if seen > rank {
+                    return HISTOGRAM_BOUNDS_MS
+                        .get(index)
+                        .map_or(histogram.max_ms, |upper| (*upper as f64).min(histogram.max_ms));
+                }
+            }
+            histogram.max_ms
+        };
+        Self {
+            count: histogram.count,
+            min_ms: histogram.min_ms,
+            mean_ms: if histogram.count == 0 {
+                0.0
+            } else {
+                histogram.sum_ms / histogram.count as f64
+            },
+            p50_ms: percentile(50.0),
+            p90_ms: percentile(90.0),
+            p99_ms: percentile(99.0),
+            max_ms: histogram.max_ms,
+            histogram: histogram
+                .counts
+                .iter()
+                .enumerate()
+                .map(|(index, count)| Bucket {
+                    upper_ms: HISTOGRAM_BOUNDS_MS.get(index).copied(),
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+    fn render(&self, name: &str, out: &mut String) {
+        writeln!(
+            out,
+            "{} n={} min={:.1} mean={:.1} p50={:.1} p90={:.1} p99={:.1} max={:.1} (ms)",
+            name, self.count, self.min_ms, self.mean_ms, self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms
+        )
+        .unwrap();
+        let widest = self.histogram.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+        for bucket in &self.histogram {
+            if bucket.count == 0 {
+                continue;
+            }
+            let label = match bucket.upper_ms {
+                Some(upper) => format!("<={}ms", upper),
+                None => format!(">{}ms", HISTOGRAM_BOUNDS_MS[HISTOGRAM_BOUNDS_MS.len() - 1]),
+            };
+            let bar = "#".repeat((bucket.count * 40).div_ceil(widest));
+            writeln!(out, "  {:>9} {:>6} {}", label, bucket.count, bar).unwrap();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    pub bots: usize,
+    pub registered: usize,
+    pub handshake: Distribution,
+    pub first_heartbeat: Distribution,
+    // 服务器在队伍列表里报的延迟 每次心跳取一个样本
+    // 心跳由服务器发起 客户端测不到往返 这里不是心跳往返时间
+    pub server_ping: Distribution,
+    pub failures: BTreeMap<&'static str, usize>,
+}
+impl LoadReport {
+    pub fn from_timings(timings: &[BotTiming], server_ping: &Histogram) -> Self {
+        let handshake: Vec<Duration> = timings.iter().filter_map(|t| t.handshake).collect();
+        let first_heartbeat: Vec<Duration> = timings.iter().filter_map(|t| t.first_heartbeat).collect();
+        let mut failures = BTreeMap::new();
+        for failure in timings.iter().filter_map(|t| t.failure) {
+            *failures.entry(failure).or_insert(0) += 1;
+        }
+        Self {
+            bots: timings.len(),
+            registered: handshake.len(),
+            handshake: Distribution::from_samples(&handshake),
+            first_heartbeat: Distribution::from_samples(&first_heartbeat),
+            server_ping: Distribution::from_histogram(server_ping),
+            failures,
+        }
+    }
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "机器人 {} 完成注册 {}", self.bots, self.registered).unwrap();
+        self.handshake.render("连接->161", &mut out);
+        self.first_heartbeat.render("161->首次心跳", &mut out);
+        self.server_ping.render("服务器报的延迟(队伍列表里的ping 不是心跳往返)", &mut out);
+        if self.failures.is_empty() {
+            writeln!(out, "没有失败").unwrap();
+        } else {
+            writeln!(out, "失败原因:").unwrap();
+            for (reason, count) in &self.failures {
+                writeln!(out, "  {:>6} {}", count, reason).unwrap();
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|v| Duration::from_millis(*v)).collect()
+    }
+
+    #[test]
+    fn percentiles_from_samples() {
+        let samples = ms(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 100]);
+        let distribution = Distribution::from_samples(&samples);
+        assert_eq!(distribution.count, 10);
+        assert_eq!(distribution.p50_ms, 6.0);
+        assert_eq!(distribution.p99_ms, 100.0);
+        assert_eq!(distribution.max_ms, 100.0);
+        assert_eq!(distribution.histogram.iter().map(|b| b.count).sum::<usize>(), 10);
+    }
+
+    #[test]
+    fn histogram_stays_bounded_and_keeps_percentiles() {
+        let mut histogram = Histogram::default();
+        for _ in 0..100_000 {
+            histogram.record(Duration::from_millis(30));
+        }
+        histogram.record(Duration::from_secs(10));
+        let distribution = Distribution::from_histogram(&histogram);
+        assert_eq!(distribution.count, 100_001);
+        assert_eq!(distribution.min_ms, 30.0);
+        assert_eq!(distribution.p50_ms, 50.0);
+        assert_eq!(distribution.max_ms, 10_000.0);
+        assert_eq!(distribution.histogram.len(), HISTOGRAM_BOUNDS_MS.len() + 1);
+        assert_eq!(distribution.histogram.last().unwrap().count, 1);
+    }
+
+    #[test]
+    fn failures_are_grouped_by_kind() {
+        let reasons = [
+            DisconnectReason::Kicked("spam".to_string()),
+            DisconnectReason::Kicked("afk".to_string()),
+            DisconnectReason::Timeout(Timeout::Heartbeat),
+            DisconnectReason::Left,
+        ];
+        let timings: Vec<BotTiming> = reasons
+            .iter()
+            .map(|reason| BotTiming {
+                failure: failure_kind(reason),
+                ..BotTiming::default()
+            })
+            .collect();
+        let report = LoadReport::from_timings(&timings, &Histogram::default());
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(report.failures["kicked"], 2);
+        assert_eq!(report.failures["timeout_heartbeat"], 1);
+    }
+}
@@ -1,46 +1,161 @@
-mod network;
-mod protocol;
-mod packet;
-mod error;
-mod packet_utils;
-
-use std::{io, net};
-use std::error::Error;
-use std::net::IpAddr;
-use std::str::FromStr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use crate::network::{make_packet, packet_con, send_packet, ToBytes};
-use crate::protocol::heart_beat::HeartBeatPacket;
-use crate::protocol::preregister_connection::PreregisterConnectionPacket;
+use std::time::Duration;
+use rwnew::client::{FakePlayer, FakePlayerBuilder};
+use rwnew::event::ClientEvent;
+use rwnew::identity::IdentityStore;
+use rwnew::reconnect::ReconnectPolicy;
+use rwnew::shutdown::{shutdown_signal, SHUTDOWN_DEADLINE};
+use rwnew::swarm::{Swarm, SwarmOptions};
 
+// 用法: rwnew [--identities=文件] [服务器地址] [机器人数量] [连接间隔毫秒] [运行秒数]
+// 给了--identities时单个机器人的uuid保存在这个文件里 重启后服务器还认得 默认不写任何文件
+// 多个机器人时开着断线重连 机器人基本不会全部结束 要靠Ctrl-C或运行秒数到了才停下
+// 两种情况都先让所有机器人离开 最多等SHUTDOWN_DEADLINE 没离开的直接结束 这期间再按一次Ctrl-C马上退出
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+    let mut identities_path = None;
+    let args: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|arg| match arg.strip_prefix("--identities=") {
+            Some(path) => {
+                identities_path = Some(path.to_string());
+                false
+            }
+            None => true,
+        })
+        .collect();
+    let address = args.first().map(String::as_str).unwrap_or("192.168.1.7:5123");
+    let count: usize = args.get(1).map(|s| s.parse()).transpose()?.unwrap_or(1);
+    let signal = shutdown_signal();
+    tokio::pin!(signal);
 
-    let mut stream = TcpStream::connect("192.168.1.7:5123").await?;
-    println!("正在连接服务器:{:?}", stream);
-
-    // 发送消息 预注册包
-    let mut packet = PreregisterConnectionPacket::new();
-    send_packet(&mut stream, &mut packet).await?;
-
-    // 读取响应
-    let mut buf = [0; 1024];
-    loop{
-        match stream.read(&mut buf).await{
-            Ok(0) => {
-                println!("连接已经关闭");
-                break;
+    if count > 1 {
+        let mut options = SwarmOptions::new(address, count);
+        options.reconnect = Some(ReconnectPolicy::default());
+        if let Some(interval) = args.get(2) {
+            options.connect_interval = Duration::from_millis(interval.parse()?);
+        }
+        let run_for = args.get(3).map(|s| s.parse().map(Duration::from_secs)).transpose()?;
+        let mut swarm = Swarm::start(options);
+        let mut report = tokio::time::interval(Duration::from_secs(5));
+        let mut reports = 0u32;
+        // 没给运行秒数时一直跑到Ctrl-C
+        let stop = async {
+            match run_for {
+                Some(duration) => tokio::time::sleep(duration).await,
+                None => std::future::pending().await,
             }
-            Ok(n) => {
-                let mut a = &buf[..n];
-                packet_con(a, &mut stream).await.expect("TODO: panic message");
+        };
+        tokio::pin!(stop);
+        loop {
+            tokio::select! {
+                _ = report.tick() => {}
+                _ = &mut stop => {
+                    println!("运行时间到了 正在让所有机器人离开");
+                    stop_swarm(&mut swarm).await;
+                    break;
+                }
+                _ = &mut signal => {
+                    println!("正在让所有机器人离开");
+                    stop_swarm(&mut swarm).await;
+                    break;
+                }
             }
-            Err(e) => {
-                eprintln!("读取错误:{}",e);
+            let summary = swarm.summary();
+            println!("机器人状态:{:?}", summary);
+            // 机器人一般一直在线 每30秒打印一次完整报告
+            reports += 1;
+            if reports.is_multiple_of(6) {
+                print!("{}", swarm.report().render());
+            }
+            let finished = summary.get("failed").unwrap_or(&0) + summary.get("disconnected").unwrap_or(&0);
+            if finished == count {
                 break;
             }
         }
+        let report = swarm.report();
+        print!("{}", report.render());
+        tokio::fs::write("loadtest.json", report.to_json()?).await?;
+        swarm.wait().await;
+        return Ok(());
+    }
+
+    let mut builder = FakePlayerBuilder::new(address).reconnect(ReconnectPolicy::default());
+    if let Some(path) = identities_path {
+        builder = builder.identity_store(IdentityStore::open(path).await?);
+    }
+    let mut player = builder.connect().await?;
+    loop {
+        tokio::select! {
+            event = player.next_event() => match event {
+                Some(ClientEvent::Disconnected { reason }) => {
+                    println!("连接结束:{:?}", reason);
+                    break;
+                }
+                Some(_) => {}
+                None => break,
+            },
+            _ = &mut signal => {
+                println!("正在离开服务器");
+                tokio::select! {
+                    _ = leave(player) => {}
+                    _ = shutdown_signal() => force_exit(),
+                }
+                return Ok(());
+            }
+        }
+    }
+    if let Err(e) = player.closed().await {
+        eprintln!("读取错误:{}",e);
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+// 离开和等会话结束一共最多SHUTDOWN_DEADLINE 超时就不等了
+async fn leave(player: FakePlayer) {
+    let deadline = tokio::time::Instant::now() + SHUTDOWN_DEADLINE;
+    match tokio::time::timeout_at(deadline, player.disconnect()).await {
+        Ok(Err(e)) => eprintln!("离开失败:{}", e),
+        Err(_) => {
+            eprintln!("{:?}内没有断开", SHUTDOWN_DEADLINE);
+            return;
+        }
+        Ok(Ok(())) => {}
+    }
+    match tokio::time::timeout_at(deadline, player.closed()).await {
+        Ok(Err(e)) => eprintln!("读取错误:{}", e),
+        Err(_) => eprintln!("{:?}内没有断开", SHUTDOWN_DEADLINE),
+        Ok(Ok(())) => {}
+    }
+}
+
+async fn stop_swarm(swarm: &mut Swarm) {
+    tokio::select! {
+        stopped = swarm.shutdown(SHUTDOWN_DEADLINE) => {
+            if !stopped {
+                eprintln!("{:?}内没有全部断开 剩下的机器人直接结束", SHUTDOWN_DEADLINE);
+            }
+        }
+        _ = shutdown_signal() => force_exit(),
+    }
+}
+
+// 库里的连接 重连和出错信息通过log输出 命令行里打印到标准错误
+struct StderrLogger;
+static LOGGER: StderrLogger = StderrLogger;
+impl log::Log for StderrLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+    fn log(&self, record: &log::Record) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+    fn flush(&self) {}
+}
+
+fn force_exit() -> ! {
+    eprintln!("再次收到退出信号 直接退出");
+    std::process::exit(130);
+}
@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use regex::{RegexSet, RegexSetBuilder};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use crate::client::{recv_event, FakePlayer};
+use crate::error::PacketError;
+use crate::event::ClientEvent;
+use crate::protocol::chat::ChatReceivePacket;
+
+#[derive(Debug, Clone)]
+pub struct ModerationRules {
+    // 正则 不区分大小写
+    pub banned_words: Vec<String>,
+    // flood_window 内超过 flood_messages 条算刷屏
+    pub flood_messages: usize,
+    pub flood_window: Duration,
+    // 违规次数达到后禁言 再达到后踢出 之前都只是警告
+    pub mute_after: u32,
+    pub kick_after: u32,
+    // 昵称
+    pub muted: HashSet<String>,
+    pub log_path: PathBuf,
+}
+impl ModerationRules {
+    pub fn new() -> Self {
+        Self {
+            banned_words: Vec::new(),
+            flood_messages: 5,
+            flood_window: Duration::from_secs(5),
+            mute_after: 2,
+            kick_after: 3,
+            muted: HashSet::new(),
+            log_path: PathBuf::from("moderation.log"),
+        }
+    }
+}
+impl Default for ModerationRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    BannedWord,
+    Flood,
+    // 禁言期间继续发言
+    Muted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModerationAction {
+    Warn { strikes: u32 },
+    Mute,
+    Kick,
+}
+
+#[derive(Debug, Default)]
+struct PlayerRecord {
+    recent: VecDeque<Instant>,
+    strikes: u32,
+}
+
+pub struct Moderator {
+    rules: ModerationRules,
+    banned: RegexSet,
+    players: HashMap<String, PlayerRecord>,
+    // 第一次写日志时打开
+    log: Option<File>,
+}
+impl Moderator {
+    // 只有违禁词的正则写错时失败
+    pub fn new(rules: ModerationRules) -> Result<Self, regex::Error> {
+        let banned = RegexSetBuilder::new(&rules.banned_words)
+            .case_insensitive(true)
+            .build()?;
+        Ok(Self {
+            rules,
+            banned,
+            players: HashMap::new(),
+            log: None,
+        })
+    }
+    pub fn is_muted(&self, nickname: &str) -> bool {
+        self.rules.muted.contains(nickname)
+    }
+    pub fn mute(&mut self, nickname: &str) {
+        self.rules.muted.insert(nickname.to_string());
+    }
+    pub fn unmute(&mut self, nickname: &str) {
+        self.rules.muted.remove(nickname);
+    }
+    fn violation(&mut self, nickname: &str, message: &str, now: Instant) -> Option<Violation> {
+        let flood_window = self.rules.flood_window;
+        let record = self.players.entry(nickname.to_string()).or_default();
+        record.recent.push_back(now);
+        while record.recent.front().is_some_and(|t| now.duration_since(*t) > flood_window) {
+            record.recent.pop_front();
+        }
+        let flooding = record.recent.len() > self.rules.flood_messages;
+        if self.is_muted(nickname) {
+            Some(Violation::Muted)
+        } else if self.banned.is_match(message) {
+            Some(Violation::BannedWord)
+        } else if flooding {
+            Some(Violation::Flood)
+        } else {
+            None
+        }
+    }
+    // 返回需要执行的处罚 日志由调用方用log写
+    pub fn check(&mut self, nickname: &str, message: &str, now: Instant) -> Option<(Violation, ModerationAction)> {
+        let violation = self.violation(nickname, message, now)?;
+        let record = self.players.get_mut(nickname).unwrap();
+        record.strikes += 1;
+        let strikes = record.strikes;
+        let action = if strikes >= self.rules.kick_after {
+            ModerationAction::Kick
+        } else if strikes >= self.rules.mute_after && !self.is_muted(nickname) {
+            self.mute(nickname);
+            ModerationAction::Mute
+        } else {
+            ModerationAction::Warn { strikes }
+        };
+        Some((violation, action))
+    }
+    pub async fn log(&mut self, nickname: &str, message: &str, violation: &Violation, action: &ModerationAction) -> std::io::Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let line = format!("{}\t{}\t{:?}\t{:?}\t{}\n", time, nickname, violation, action, message);
+        let log = match &mut self.log {
+            Some(log) => log,
+            None => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.rules.log_path)
+                    .await?;
+                self.log.insert(file)
+            }
+        };
+        log.write_all(line.as_bytes()).await
+    }
+}
+
+impl FakePlayer {
+    // 机器人当房主或管理员时管理聊天 直到连接关闭
+    pub async fn run_moderation(&self, mut moderator: Moderator) -> Result<(), PacketError> {
+        let mut events = self.subscribe();
+        let mut my_slot = None;
+        while let Some(event) = recv_event(&mut events).await {
+            let chat = match event {
+                ClientEvent::RosterUpdated(list) => {
+                    my_slot = Some(list.my_slot);
+                    continue;
+                }
+                ClientEvent::Lagged { missed } => {
+                    log::warn!("处理太慢 漏看了{}条消息", missed);
+                    continue;
+                }
+                ClientEvent::Chat(chat) => chat,
+                _ => continue,
+            };
+            // 跳过系统消息和自己发的
+            if chat.sender.is_empty() || Some(chat.slot) == my_slot {
+                continue;
+            }
+            if let Some((violation, action)) = moderator.check(&chat.sender, &chat.message, Instant::now()) {
+                if let Err(e) = moderator.log(&chat.sender, &chat.message, &violation, &action).await {
+                    log::warn!("写入管理日志失败:{}", e);
+                }
+                // 玩家可能已经离开 处罚失败不影响后面的管理
+                if let Err(e) = self.enforce(&chat, &violation, &action).await {
+                    log::warn!("处罚{}失败:{}", chat.sender, e);
+                }
+            }
+        }
+        Ok(())
+    }
+    async fn enforce(&self, chat: &ChatReceivePacket, violation: &Violation, action: &ModerationAction) -> Result<(), PacketError> {
+        let reason = match violation {
+            Violation::BannedWord => "违禁词",
+            Violation::Flood => "刷屏",
+            Violation::Muted => "禁言中发言",
+        };
+        match action {
+            ModerationAction::Warn { strikes } => {
+                self.send_chat(&format!("{} 警告({}): {}", chat.sender, strikes, reason))
+            }
+            ModerationAction::Mute => {
+                self.send_chat(&format!("{} 已被禁言: {}", chat.sender, reason))
+            }
+            ModerationAction::Kick => self.kick(chat.slot, reason).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moderator() -> Moderator {
+        Moderator::new(ModerationRules {
+            banned_words: vec!["bad\\s*word".to_string()],
+            ..ModerationRules::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn escalates_from_warning_to_kick() {
+        let mut moderator = moderator();
+        let now = Instant::now();
+        assert_eq!(moderator.check("a", "hello", now), None);
+        assert_eq!(
+            moderator.check("a", "BAD word", now),
+            Some((Violation::BannedWord, ModerationAction::Warn { strikes: 1 }))
+        );
+        assert_eq!(
+            moderator.check("a", "badword", now),
+            Some((Violation::BannedWord, ModerationAction::Mute))
+        );
+        assert!(moderator.is_muted("a"));
+        assert_eq!(moderator.check("a", "hi", now), Some((Violation::Muted, ModerationAction::Kick)));
+        assert!(!moderator.is_muted("b"));
+    }
+
+    #[test]
+    fn detects_flooding_within_the_window() {
+        let mut moderator = moderator();
+        let now = Instant::now();
+        for i in 0..5 {
+            assert_eq!(moderator.check("a", "hi", now + Duration::from_millis(i)), None);
+        }
+        assert_eq!(
+            moderator.check("a", "hi", now + Duration::from_millis(5)),
+            Some((Violation::Flood, ModerationAction::Warn { strikes: 1 }))
+        );
+        // 窗口过去后重新计数
+        assert_eq!(moderator.check("a", "hi", now + Duration::from_secs(20)), None);
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let rules = ModerationRules {
+            banned_words: vec!["(".to_string()],
+            ..ModerationRules::default()
+        };
+        assert!(Moderator::new(rules).is_err());
+    }
+}
@@ -1,13 +1,6 @@
-use chrono::{Local, Utc};
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 use crate::error::PacketError;
-use crate::packet::Packet;
-use crate::protocol::heart::HeartPacket;
-use crate::protocol::heart_beat::HeartBeatPacket;
-use crate::protocol::player_info::PlayerInfoPacket;
-use crate::protocol::preregister_connection::PreregisterConnectionPacket;
-use crate::protocol::register_connection::RegisterConnectionPacket;
+use crate::packet::{Packet, PacketWriter};
 
 #[derive(Debug, PartialEq)]
 pub struct PacketModel {
@@ -15,52 +8,117 @@ pub struct PacketModel {
     pub total_length: i32,
 }
 pub trait ToBytes {
-    fn to_bytes(&self) -> Result<Vec<u8>, PacketError>;
+    // 把整个包连同包头追加到out后面 多个包可以写进同一个缓冲区
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError>;
+    fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
+        let mut out = PacketWriter::new();
+        self.encode(&mut out)?;
+        Ok(out.into_vec())
+    }
 }
 pub trait FromBytes :Sized {
     fn from_packet(packet: &mut Packet) -> Result<Self, PacketError>;
 }
 pub fn make_packet<T: ToBytes>(packet: &T) -> Vec<u8> {
-    let mut packet1 = packet.to_bytes().unwrap();
-    packet1
+    packet.to_bytes().unwrap()
 }
 impl FromBytes for PacketModel {
     fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
         let total_length = packet.read_i32()?;
-        let model = packet.read_i32()?;;
+        let model = packet.read_i32()?;
         Ok(Self { total_length,model })
     }
 }
-pub async fn packet_con(a:&[u8], stream: &mut TcpStream) -> Result<(), PacketError> {
-    let mut packet1 = Packet{
-        payload: Vec::from(a),
-        offset: 0,
-    };
-    let packet_type = PacketModel::from_packet(&mut packet1)?;
-    packet1.offset = 0;
-    match packet_type.model {
-         161 =>{
-             println!("收到161数据包 正在发送注册包");
-             let mut packet = PlayerInfoPacket::new(&mut packet1);
-             println!("{:?}",packet);
-             send_packet(stream, &mut packet).await.expect("");
-         }
-         108 =>{
-            let b = HeartPacket::from_packet(&mut packet1)?;
-            let mut packet = HeartBeatPacket::new(b.ping_number);
-             println!("{:?}",packet);
-            send_packet(stream, &mut packet).await.expect("");
-         }
-
-        _ => {
-        }
-    }
-    Ok(())
-}
-pub async fn send_packet<T: ToBytes>(stream :&mut TcpStream, packet: &T) -> Result<(), Box<dyn std::error::Error>> {
-    let msg = make_packet(packet);
-    stream.write_all(&msg).await?;
+pub async fn send_packet<W: AsyncWrite + Unpin, T: ToBytes>(stream :&mut W, packet: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = PacketWriter::new();
+    packet.encode(&mut out)?;
+    stream.write_all(&out.payload).await?;
     Ok(())
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::chat::ChatPacket;
+    use crate::protocol::game_command::{CommandAction, GameCommand};
+    use crate::protocol::heart_beat::HeartBeatPacket;
+    use crate::protocol::player_info::PlayerInfoPacket;
+    use crate::protocol::preregister_connection::PreregisterConnectionPacket;
+    use crate::protocol::register_connection::RegisterConnectionPacket;
+
+    // 下面的十六进制都是改用begin_frame/finish_frame之前的编码器输出的 必须一个字节都不差
+    fn hex<T: ToBytes>(packet: &T) -> String {
+        packet.to_bytes().unwrap().iter().map(|b| format!("{:02x}", b)).collect()
+    }
 
+    #[test]
+    fn chat_and_heartbeat_match_the_old_encoder() {
+        assert_eq!(hex(&ChatPacket::new("hello 你好")), "0000000f0000008c000c68656c6c6f20e4bda0e5a5bd00");
+        assert_eq!(hex(&HeartBeatPacket::new(123456789)), "0000000a0000006d00000000075bcd15013a");
+    }
+
+    #[test]
+    fn game_commands_match_the_old_encoder() {
+        let build = CommandAction::Build {
+            unit_type: "tank".into(),
+            x: 1.0,
+            y: 2.5,
+        };
+        assert_eq!(
+            hex(&GameCommand::new(1, vec![1, 2, 3], build)),
+            concat!(
+                "0000002c00000014010300000003000000000000000100000000000000020000",
+                "000000000003000474616e6b3f80000040200000",
+            )
+        );
+        assert_eq!(hex(&GameCommand::new(0, vec![], CommandAction::Surrender)), "0000000600000014000600000000");
+    }
 
+    #[test]
+    fn handshake_packets_match_the_old_encoder() {
+        assert_eq!(
+            hex(&PreregisterConnectionPacket::new()),
+            concat!(
+                "00000032000000a00016636f6d2e636f72726f64696e6767616d65732e727473",
+                "00000004000000b00000000200000577616e616e00027a680000",
+            )
+        );
+        let mut register = RegisterConnectionPacket::new();
+        register.network_server_id = "6f9619ff-8b86-d011-b42d-00c04fc964ff".into();
+        register.server_key = 7;
+        register.color = 3;
+        assert_eq!(
+            hex(&register),
+            concat!(
+                "00000073000000a10016636f6d2e636f72726f64696e6767616d65732e727473",
+                "00000002000000b0000000b0001b636f6d2e636f72726f64696e6767616d6573",
+                "2e7274732e6a617661002436663936313966662d386238362d643031312d6234",
+                "32642d303063303466633936346666000000070000000300000000",
+            )
+        );
+        let mut packet = Packet::new(register.to_bytes().unwrap());
+        let info = PlayerInfoPacket::new(&mut packet, "nick", "00000000-0000-0000-0000-000000000001", None, false).unwrap();
+        assert_eq!(
+            hex(&info),
+            concat!(
+                "000000f40000006e0016636f6d2e636f72726f64696e6767616d65732e727473",
+                "00000005000000b0000000b000046e69636b00001b636f6d2e636f72726f6469",
+                "6e6767616d65732e7274732e6a61766100404638334343373134443744433845",
+                "3736453737414534364641463745444538374432443934333432303144303746",
+                "323645393030453338344436383831353531286ef231005b633a376d3a363333",
+                "303a333038303030313a37323a3931303030333a3238303037343a3532353030",
+                "30353a313630303037363a35393530303030373a3132363030303030383a3236",
+                "36303030303074313a332e30384535643a3335000723303030303033",
+            )
+        );
+    }
+
+    #[test]
+    fn packets_share_one_buffer() {
+        let mut out = PacketWriter::new();
+        ChatPacket::new("hello 你好").encode(&mut out).unwrap();
+        HeartBeatPacket::new(123456789).encode(&mut out).unwrap();
+        let mut expected = ChatPacket::new("hello 你好").to_bytes().unwrap();
+        expected.extend(HeartBeatPacket::new(123456789).to_bytes().unwrap());
+        assert_eq!(out.into_vec(), expected);
+    }
+}
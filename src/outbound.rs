@@ -0,0 +1,257 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use crate::client::closed_error;
+use crate::error::PacketError;
+use crate::network::ToBytes;
+use crate::packet::PacketWriter;
+
+// 一次写出去的最大字节数 超过后先写 剩下的下一轮再取
+pub const MAX_BATCH: usize = 64 * 1024;
+// 低优先级队列最多排这么多个 连接很慢时再发聊天返回QueueFull
+pub const LOW_QUEUE_CAPACITY: usize = 256;
+
+// 写任务总是先发高优先级的包
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // 心跳回复 晚了会被服务器踢掉
+    High = 0,
+    // 注册 大厅修改 房主操作 游戏命令
+    Normal = 1,
+    // 聊天
+    Low = 2,
+}
+
+// 排队的包带着排队时连接的代数 换了连接以后旧的包不再发
+type Frame = (u64, Bytes);
+
+// 任何任务都可以拿一份往连接里发包 重连后继续可用
+#[derive(Clone)]
+pub struct Sender {
+    high: mpsc::UnboundedSender<Frame>,
+    normal: mpsc::UnboundedSender<Frame>,
+    low: mpsc::Sender<Frame>,
+    generation: Arc<AtomicU64>,
+}
+impl Sender {
+    pub fn send<T: ToBytes>(&self, packet: &T, priority: Priority) -> Result<(), PacketError> {
+        let mut out = PacketWriter::new();
+        packet.encode(&mut out)?;
+        self.send_frames(out, priority)
+    }
+    // 缓冲区里的包作为一个整体排队 不会被别的包插到中间
+    pub fn send_frames(&self, out: PacketWriter, priority: Priority) -> Result<(), PacketError> {
+        self.send_bytes(out.payload.freeze(), priority)
+    }
+    pub fn send_bytes(&self, frames: Bytes, priority: Priority) -> Result<(), PacketError> {
+        let frame = (self.generation.load(Ordering::Acquire), frames);
+        match priority {
+            Priority::High => self.high.send(frame).map_err(|_| closed_error()),
+            Priority::Normal => self.normal.send(frame).map_err(|_| closed_error()),
+            Priority::Low => self.low.try_send(frame).map_err(|e| match e {
+                TrySendError::Full(_) => PacketError::QueueFull,
+                TrySendError::Closed(_) => closed_error(),
+            }),
+        }
+    }
+    // 换新连接前调用 之前排队的包都算旧连接的
+    pub(crate) fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+}
+
+// 任何连接的写端
+pub(crate) type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+
+pub(crate) enum Control {
+    // 换上新连接的写端 写失败时通过oneshot通知
+    // 代数小于给出的代数的包是给旧连接的 直接丢掉
+    Attach(WriteHalf, oneshot::Sender<PacketError>, u64),
+    // 把已经排队的包写完 最后写farewell 再关掉当前连接的写端 完成后通知done
+    Detach {
+        farewell: Option<Bytes>,
+        done: Option<oneshot::Sender<Result<(), PacketError>>>,
+    },
+}
+
+struct Queues {
+    high: mpsc::UnboundedReceiver<Frame>,
+    normal: mpsc::UnboundedReceiver<Frame>,
+    low: mpsc::Receiver<Frame>,
+    // 当前连接的代数
+    generation: u64,
+}
+impl Queues {
+    async fn recv(&mut self) -> Option<Bytes> {
+        loop {
+            let (generation, frame) = tokio::select! {
+                biased;
+                Some(frame) = self.high.recv() => frame,
+                Some(frame) = self.normal.recv() => frame,
+                Some(frame) = self.low.recv() => frame,
+                else => return None,
+            };
+            if generation >= self.generation {
+                return Some(frame);
+            }
+        }
+    }
+    fn try_recv(&mut self) -> Option<Bytes> {
+        loop {
+            let (generation, frame) = self
+                .high
+                .try_recv()
+                .or_else(|_| self.normal.try_recv())
+                .or_else(|_| self.low.try_recv())
+                .ok()?;
+            if generation >= self.generation {
+                return Some(frame);
+            }
+        }
+    }
+}
+
+pub(crate) fn channel() -> (Sender, Writer) {
+    let (high_tx, high_rx) = mpsc::unbounded_channel();
+    let (normal_tx, normal_rx) = mpsc::unbounded_channel();
+    let (low_tx, low_rx) = mpsc::channel(LOW_QUEUE_CAPACITY);
+    let sender = Sender {
+        high: high_tx,
+        normal: normal_tx,
+        low: low_tx,
+        generation: Arc::new(AtomicU64::new(0)),
+    };
+    let writer = Writer {
+        queues: Queues {
+            high: high_rx,
+            normal: normal_rx,
+            low: low_rx,
+            generation: 0,
+        },
+    };
+    (sender, writer)
+}
+
+// 写任务 和读任务分开 慢的写不会耽误读和心跳
+pub(crate) struct Writer {
+    queues: Queues,
+}
+impl Writer {
+    // control关闭时结束
+    pub(crate) async fn run(mut self, mut control: mpsc::UnboundedReceiver<Control>) {
+        let mut current: Option<(WriteHalf, oneshot::Sender<PacketError>)> = None;
+        let mut out = BytesMut::new();
+        loop {
+            tokio::select! {
+                biased;
+                message = control.recv() => match message {
+                    Some(Control::Attach(half, failed, generation)) => {
+                        self.queues.generation = generation;
+                        current = Some((half, failed));
+                    }
+                    Some(Control::Detach { farewell, done }) => {
+                        let mut result = Ok(());
+                        if let Some((mut half, _)) = current.take() {
+                            while let Some(frame) = self.queues.try_recv() {
+                                out.extend_from_slice(&frame);
+                            }
+                            if let Some(farewell) = farewell {
+                                out.extend_from_slice(&farewell);
+                            }
+                            result = async {
+                                half.write_all(&out).await?;
+                                half.shutdown().await
+                            }
+                            .await
+                            .map_err(|e| PacketError::IoError(e.to_string()));
+                            out.clear();
+                        }
+                        if let Some(done) = done {
+                            let _ = done.send(result);
+                        }
+                    }
+                    None => return,
+                },
+                // 没有连接时包留在队列里 重连后只发新连接的 旧的心跳回复和命令对新连接没有意义
+                frame = self.queues.recv(), if current.is_some() => {
+                    let Some(frame) = frame else { return };
+                    out.extend_from_slice(&frame);
+                    while out.len() < MAX_BATCH {
+                        match self.queues.try_recv() {
+                            Some(frame) => out.extend_from_slice(&frame),
+                            None => break,
+                        }
+                    }
+                    let (half, _) = current.as_mut().unwrap();
+                    if let Err(e) = half.write_all(&out).await {
+                        let (_, failed) = current.take().unwrap();
+                        let _ = failed.send(PacketError::IoError(e.to_string()));
+                    }
+                    out.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, DuplexStream};
+    use super::*;
+
+    fn attach(control: &mpsc::UnboundedSender<Control>, sender: &Sender) -> DuplexStream {
+        let (ours, theirs) = tokio::io::duplex(1024);
+        let (failed, _) = oneshot::channel();
+        control
+            .send(Control::Attach(Box::new(ours), failed, sender.next_generation()))
+            .unwrap();
+        theirs
+    }
+
+    async fn read_exact(stream: &mut DuplexStream, len: usize) -> Vec<u8> {
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn low_priority_queue_is_bounded() {
+        let (sender, _writer) = channel();
+        for _ in 0..LOW_QUEUE_CAPACITY {
+            sender.send_bytes(Bytes::from_static(b"chat"), Priority::Low).unwrap();
+        }
+        assert_eq!(
+            sender.send_bytes(Bytes::from_static(b"chat"), Priority::Low),
+            Err(PacketError::QueueFull)
+        );
+        // 其他优先级不受影响
+        sender.send_bytes(Bytes::from_static(b"beat"), Priority::High).unwrap();
+    }
+
+    #[tokio::test]
+    async fn frames_for_the_old_connection_are_dropped_on_attach() {
+        let (sender, writer) = channel();
+        let (control, control_rx) = mpsc::unbounded_channel();
+        tokio::spawn(writer.run(control_rx));
+        let mut first = attach(&control, &sender);
+        sender.send_bytes(Bytes::from_static(b"one"), Priority::Normal).unwrap();
+        assert_eq!(read_exact(&mut first, 3).await, b"one");
+
+        // 断开期间排队的包
+        control.send(Control::Detach { farewell: None, done: None }).unwrap();
+        tokio::task::yield_now().await;
+        sender.send_bytes(Bytes::from_static(b"old"), Priority::High).unwrap();
+        sender.send_bytes(Bytes::from_static(b"old"), Priority::Low).unwrap();
+
+        let mut second = attach(&control, &sender);
+        sender.send_bytes(Bytes::from_static(b"new"), Priority::Normal).unwrap();
+        assert_eq!(read_exact(&mut second, 3).await, b"new");
+        drop(control);
+        let mut rest = Vec::new();
+        second.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+}
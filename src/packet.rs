@@ -1,30 +1,173 @@
-use std::convert::TryInto;
+use bytes::{BufMut, Bytes, BytesMut};
 use crate::error::PacketError;
 
+// 读包的游标 基本类型直接从切片解码不分配内存
+// 字符串和字节数组默认借用 需要所有权时再用read_string和read_bytes
 pub struct Packet {
-    pub payload: Vec<u8>,
+    pub payload: Bytes,
     pub offset: usize,
 }
 
 impl Packet {
-    pub fn new() -> Self {
+    // Vec转成Bytes不会复制
+    pub fn new(payload: impl Into<Bytes>) -> Self {
         Self {
-            payload: Vec::new(),
+            payload: payload.into(),
             offset: 0,
         }
     }
 
+    pub fn remaining(&self) -> usize {
+        self.payload.len().saturating_sub(self.offset)
+    }
+
+    fn advance(&mut self, len: usize) -> Result<(usize, usize), PacketError> {
+        let start = self.offset;
+        let end = match start.checked_add(len) {
+            Some(e) => e,
+            None => return Err(PacketError::OutOfBounds),
+        };
+        if end > self.payload.len() {
+            return Err(PacketError::OutOfBounds);
+        }
+        self.offset = end;
+        Ok((start, end))
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], PacketError> {
+        let (start, end) = self.advance(N)?;
+        Ok(self.payload[start..end].try_into().unwrap())
+    }
+
+    pub fn read_slice(&mut self, len: usize) -> Result<&[u8], PacketError> {
+        let (start, end) = self.advance(len)?;
+        Ok(&self.payload[start..end])
+    }
+
+    // 和payload共用同一块内存 只增加引用计数
+    pub fn read_bytes(&mut self, len: usize) -> Result<Bytes, PacketError> {
+        let (start, end) = self.advance(len)?;
+        Ok(self.payload.slice(start..end))
+    }
+
+    // 读一个数量 后面每个元素至少min_len字节
+    // 数量超过剩下的字节能装下的个数时直接报错 恶意的数量不会导致巨大的内存分配
+    pub fn read_count(&mut self, min_len: usize) -> Result<usize, PacketError> {
+        let count = usize::try_from(self.read_i32()?).map_err(|_| PacketError::OutOfBounds)?;
+        if count.saturating_mul(min_len.max(1)) > self.remaining() {
+            return Err(PacketError::OutOfBounds);
+        }
+        Ok(count)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, PacketError> {
+        Ok(self.read_byte()? != 0)
+    }
+
+    pub fn read_byte(&mut self) -> Result<u8, PacketError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, PacketError> {
+        Ok(i16::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, PacketError> {
+        Ok(i32::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, PacketError> {
+        Ok(i64::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, PacketError> {
+        Ok(f32::from_be_bytes(self.read_array()?))
+    }
+
+    pub fn read_str(&mut self) -> Result<&str, PacketError> {
+        let len = self.read_i16()? as usize;
+        let bytes = self.read_slice(len)?;
+        std::str::from_utf8(bytes).map_err(Into::into)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, PacketError> {
+        self.read_str().map(str::to_owned)
+    }
+
+    // 前面有一个bool 为false时是空字符串
+    pub fn read_is_str(&mut self) -> Result<&str, PacketError> {
+        if !self.read_bool()? {
+            return Ok("");
+        }
+        self.read_str()
+    }
+
+    pub fn read_is_string(&mut self) -> Result<String, PacketError> {
+        self.read_is_str().map(str::to_owned)
+    }
+}
+
+// 包头为长度和类型各4字节 长度不含包头
+pub const FRAME_HEADER_LEN: usize = 8;
+
+// 写包的缓冲区 可以连续写多个包 最后一次write_all发出去
+pub struct PacketWriter {
+    pub payload: BytesMut,
+}
+
+impl Default for PacketWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketWriter {
+    pub fn new() -> Self {
+        Self {
+            payload: BytesMut::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            payload: BytesMut::with_capacity(capacity),
+        }
+    }
+
+    // 先占住长度的位置 写完包体后由finish_frame回填 返回包头的位置
+    pub fn begin_frame(&mut self, packet_type: i32) -> Result<usize, PacketError> {
+        let start = self.payload.len();
+        self.write_i32(0)?;
+        self.write_i32(packet_type)?;
+        Ok(start)
+    }
+
+    pub fn finish_frame(&mut self, start: usize) -> Result<(), PacketError> {
+        let body_length = self.payload.len() - start - FRAME_HEADER_LEN;
+        let total_length = i32::try_from(body_length).map_err(|_| PacketError::OutOfBounds)?;
+        self.payload[start..start + 4].copy_from_slice(&total_length.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.payload.into()
+    }
+
     pub fn write_i16(&mut self, value: i16) -> Result<(), PacketError> {
-        self.payload.extend_from_slice(&value.to_be_bytes());
+        self.payload.put_i16(value);
         Ok(())
     }
 
     pub fn write_i32(&mut self, value: i32) -> Result<(), PacketError> {
-        self.payload.extend_from_slice(&value.to_be_bytes());
+        self.payload.put_i32(value);
         Ok(())
     }
     pub fn write_i64(&mut self, value: i64) -> Result<(), PacketError> {
-        self.payload.extend_from_slice(&value.to_be_bytes());
+        self.payload.put_i64(value);
+        Ok(())
+    }
+    pub fn write_f32(&mut self, value: f32) -> Result<(), PacketError> {
+        self.payload.put_f32(value);
         Ok(())
     }
 
@@ -33,7 +176,7 @@ impl Packet {
         Ok(())
     }
     pub fn write_byte(&mut self, value: u8) -> Result<(), PacketError> {
-        self.payload.push(value);
+        self.payload.put_u8(value);
         Ok(())
     }
 
@@ -58,58 +201,17 @@ impl Packet {
     }
 
     pub fn write_bool(&mut self, value: bool) -> Result<(), PacketError> {
-        self.payload.push(value as u8);
+        self.payload.put_u8(value as u8);
         Ok(())
     }
+}
 
-    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, PacketError> {
-        let end = match self.offset.checked_add(len) {
-            Some(e) => e,
-            None => return Err(PacketError::OutOfBounds),
-        };
-        if end > self.payload.len() {
-            return Err(PacketError::OutOfBounds);
-        }
-        let bytes = self.payload[self.offset..end].to_vec();
-        self.offset = end;
-        Ok(bytes)
-    }
-
-    pub fn read_bool(&mut self) -> Result<bool, PacketError> {
-        Ok(self.read_bytes(1)?[0] != 0)
-    }
-
-    pub fn read_byte(&mut self) -> Result<u8, PacketError> {
-        Ok(self.read_bytes(1)?[0])
-    }
-
-    pub fn read_i16(&mut self) -> Result<i16, PacketError> {
-        let bytes = self.read_bytes(2)?;
-        Ok(i16::from_be_bytes(bytes.try_into().unwrap()))
-    }
-
-    pub fn read_i32(&mut self) -> Result<i32, PacketError> {
-        let bytes = self.read_bytes(4)?;
-        Ok(i32::from_be_bytes(bytes.try_into().unwrap()))
-    }
-
-    pub fn read_i64(&mut self) -> Result<i64, PacketError> {
-        let bytes = self.read_bytes(8)?;
-        Ok(i64::from_be_bytes(bytes.try_into().unwrap()))
-    }
-
-    pub fn read_string(&mut self) -> Result<String, PacketError> {
-        let len = self.read_i16()? as usize;
-        let bytes = self.read_bytes(len)?;
-        String::from_utf8(bytes).map_err(Into::into)
-    }
-
-    pub fn read_is_string(&mut self) -> Result<String, PacketError> {
-        if !self.read_bool()? {
-            return Ok(String::new());
-        }
-        let len = self.read_i16()? as usize;
-        let bytes = self.read_bytes(len)?;
-        String::from_utf8(bytes).map_err(Into::into)
-    }
-}
\ No newline at end of file
+// 测试里拼一个完整的帧 body里写包体
+#[cfg(test)]
+pub(crate) fn frame(packet_type: i32, body: impl FnOnce(&mut PacketWriter)) -> Vec<u8> {
+    let mut out = PacketWriter::new();
+    let start = out.begin_frame(packet_type).unwrap();
+    body(&mut out);
+    out.finish_frame(start).unwrap();
+    out.into_vec()
+}
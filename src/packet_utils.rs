@@ -1,8 +1,8 @@
-use std::collections::hash_map::Keys;
 use std::fmt::Write;
 use num_bigint::BigInt;
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
+use crate::error::PacketError;
 use crate::packet::Packet;
 use crate::protocol::register_connection::RegisterConnectionPacket;
 pub struct SerKey {
@@ -10,6 +10,11 @@ pub struct SerKey {
     pub network_id: String,
     pub color :i32
 }
+impl Default for SerKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl SerKey {
     pub fn new() -> Self {
         Self {
@@ -18,11 +23,12 @@ impl SerKey {
             color: 0,
         }
     }
-    pub fn get(a: &mut SerKey, packet: &mut Packet) {
-        let b = RegisterConnectionPacket::from_packet(packet).unwrap();
+    pub fn get(a: &mut SerKey, packet: &mut Packet) -> Result<(), PacketError> {
+        let b = RegisterConnectionPacket::from_packet(packet)?;
         a.keys = b.server_key;
         a.network_id = b.network_server_id;
         a.color = b.color;
+        Ok(())
     }
 }
 fn format_scientific(n: &BigInt) -> String {
@@ -127,9 +133,9 @@ fn uuid_to_csharp_guid_bytes(uuid: Uuid) -> [u8; 16] {
     csharp_bytes
 }
 
-pub fn compute_uuid_for_packet(client_uuid: &str, server_uuid: &str) -> String {
-    let client_guid = Uuid::parse_str(client_uuid).expect("Invalid client UUID");
-    let server_guid = Uuid::parse_str(server_uuid).expect("Invalid server UUID");
+pub fn compute_uuid_for_packet(client_uuid: &str, server_uuid: &str) -> Result<String, PacketError> {
+    let client_guid = Uuid::parse_str(client_uuid).map_err(|e| PacketError::InvalidUuid(format!("client {}: {}", client_uuid, e)))?;
+    let server_guid = Uuid::parse_str(server_uuid).map_err(|e| PacketError::InvalidUuid(format!("server {}: {}", server_uuid, e)))?;
 
     let client_bytes = uuid_to_csharp_guid_bytes(client_guid);
     let server_bytes = uuid_to_csharp_guid_bytes(server_guid);
@@ -140,5 +146,5 @@ pub fn compute_uuid_for_packet(client_uuid: &str, server_uuid: &str) -> String {
     let sum_guid = client_num + server_num;
     let sum_bytes = sum_guid.to_signed_bytes_le();
 
-    compute_sha256_hash(&sum_bytes)
+    Ok(compute_sha256_hash(&sum_bytes))
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+pub const DEFAULT_MAX_POOLED: usize = 1024;
+// 按线程分成几份 上万个机器人不会都抢同一把锁
+pub const POOL_SHARDS: usize = 16;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+thread_local! {
+    // 每个线程第一次用的时候轮流分一份
+    static SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+fn current_shard() -> usize {
+    SHARD.with(|shard| match shard.get() {
+        Some(index) => index,
+        None => {
+            let index = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % POOL_SHARDS;
+            shard.set(Some(index));
+            index
+        }
+    })
+}
+
+// 读缓冲区的共享池 连接空闲时把缓冲区还回来 空闲的机器人不占读缓冲区
+pub struct BufferPool {
+    shards: [Mutex<Vec<Vec<u8>>>; POOL_SHARDS],
+    buffer_size: usize,
+    // 每一份最多留这么多 总数不超过max_pooled
+    max_per_shard: usize,
+}
+impl BufferPool {
+    pub fn new(buffer_size: usize, max_pooled: usize) -> Arc<Self> {
+        Arc::new(Self {
+            shards: std::array::from_fn(|_| Mutex::new(Vec::new())),
+            buffer_size,
+            max_per_shard: max_pooled / POOL_SHARDS,
+        })
+    }
+    // 所有客户端默认共用这一个
+    pub fn shared() -> Arc<Self> {
+        static SHARED: OnceLock<Arc<BufferPool>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| BufferPool::new(DEFAULT_BUFFER_SIZE, DEFAULT_MAX_POOLED))
+            .clone()
+    }
+    // 只看当前线程那一份 空了就新分配 不去别的线程那里找
+    pub fn take(&self) -> Vec<u8> {
+        self.shards[current_shard()]
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.buffer_size))
+    }
+    pub fn put(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        // 收过大包的缓冲区缩回默认大小再放回去
+        buffer.shrink_to(self.buffer_size);
+        let mut buffers = self.shards[current_shard()].lock().unwrap();
+        if buffers.len() < self.max_per_shard {
+            buffers.push(buffer);
+        }
+    }
+    pub fn pooled(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_buffers_up_to_the_limit() {
+        let pool = BufferPool::new(16, POOL_SHARDS * 2);
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(&[0; 1024]);
+        pool.put(buffer);
+        assert_eq!(pool.pooled(), 1);
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() < 1024);
+        assert_eq!(pool.pooled(), 0);
+        for _ in 0..10 {
+            pool.put(Vec::new());
+        }
+        assert_eq!(pool.pooled(), 2);
+    }
+
+    #[test]
+    fn threads_use_separate_shards() {
+        let pool = BufferPool::new(16, POOL_SHARDS * 4);
+        let mine = current_shard();
+        // 其他线程还回来的缓冲区留在它们自己那一份
+        let other = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    pool.put(Vec::new());
+                    current_shard()
+                })
+                .join()
+                .unwrap()
+        });
+        assert_eq!(pool.pooled(), 1);
+        if other != mine {
+            assert_eq!(pool.shards[mine].lock().unwrap().len(), 0);
+            assert_eq!(pool.shards[other].lock().unwrap().len(), 1);
+        }
+    }
+}
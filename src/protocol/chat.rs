@@ -0,0 +1,55 @@
+//140 packet
+use crate::error::PacketError;
+use crate::network::ToBytes;
+use crate::packet::{Packet, PacketWriter};
+
+pub const PACKET_CHAT: i32 = 140;
+
+#[derive(Debug, PartialEq)]
+pub struct ChatPacket {
+    pub message: String,
+    pub unknown_byte: u8,
+}
+impl ChatPacket {
+    pub fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            unknown_byte: 0,
+        }
+    }
+}
+impl ToBytes for ChatPacket {
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_CHAT)?;
+        out.write_string(&self.message)?;
+        out.write_byte(self.unknown_byte)?;
+        out.finish_frame(frame)
+    }
+}
+
+pub const PACKET_CHAT_RECEIVE: i32 = 141;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatReceivePacket {
+    pub message: String,
+    // 系统消息没有发送者
+    pub sender: String,
+    pub slot: i32,
+}
+impl ChatReceivePacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_CHAT_RECEIVE {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let message = packet.read_string()?;
+        let sender = packet.read_is_string()?;
+        let slot = packet.read_i32()?;
+        Ok(Self {
+            message,
+            sender,
+            slot,
+        })
+    }
+}
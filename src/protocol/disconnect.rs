@@ -0,0 +1,31 @@
+//111 packet
+use crate::error::PacketError;
+use crate::network::ToBytes;
+use crate::packet::PacketWriter;
+
+pub const PACKET_DISCONNECT: i32 = 111;
+
+// 主动离开时发给服务器 服务器收到后马上移除玩家
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisconnectPacket {
+    pub reason: String,
+}
+impl DisconnectPacket {
+    pub fn new() -> Self {
+        Self {
+            reason: "exited".to_string(),
+        }
+    }
+}
+impl Default for DisconnectPacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ToBytes for DisconnectPacket {
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_DISCONNECT)?;
+        out.write_string(&self.reason)?;
+        out.finish_frame(frame)
+    }
+}
@@ -0,0 +1,166 @@
+//20 packet
+use crate::error::PacketError;
+use crate::network::ToBytes;
+use crate::packet::{Packet, PacketWriter};
+
+pub const PACKET_ADD_GAME_COMMAND: i32 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandAction {
+    Select,
+    Move { x: f32, y: f32 },
+    Attack { target: i64 },
+    Build { unit_type: String, x: f32, y: f32 },
+    SetRally { x: f32, y: f32 },
+    Stop,
+    Surrender,
+}
+
+impl CommandAction {
+    pub fn id(&self) -> u8 {
+        match self {
+            CommandAction::Select => 0,
+            CommandAction::Move { .. } => 1,
+            CommandAction::Attack { .. } => 2,
+            CommandAction::Build { .. } => 3,
+            CommandAction::SetRally { .. } => 4,
+            CommandAction::Stop => 5,
+            CommandAction::Surrender => 6,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandAction::Select => "select",
+            CommandAction::Move { .. } => "move",
+            CommandAction::Attack { .. } => "attack",
+            CommandAction::Build { .. } => "build",
+            CommandAction::SetRally { .. } => "set_rally",
+            CommandAction::Stop => "stop",
+            CommandAction::Surrender => "surrender",
+        }
+    }
+}
+
+// 协议里的命令不带tick 在哪个tick执行由服务器决定
+// 客户端只能保证在收到下一个tick后发出 不能指定目标tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameCommand {
+    // 发出命令的玩家位置 就是服务器里的site 和队伍列表里的slot一样 不是队伍
+    // 服务器会检查这个位置是不是发送者自己的
+    pub slot: u8,
+    pub units: Vec<i64>,
+    pub action: CommandAction,
+}
+impl GameCommand {
+    // 位置 动作 单位数量
+    pub const MIN_BODY_LEN: usize = 6;
+
+    pub fn new(slot: u8, units: Vec<i64>, action: CommandAction) -> Self {
+        Self {
+            slot,
+            units,
+            action,
+        }
+    }
+    // 命令本体 不含包头 tick包里也是这个格式
+    pub fn read_body(packet: &mut Packet) -> Result<Self, PacketError> {
+        let slot = packet.read_byte()?;
+        let action_id = packet.read_byte()?;
+        let unit_count = packet.read_count(8)?;
+        let mut units = Vec::with_capacity(unit_count);
+        for _ in 0..unit_count {
+            units.push(packet.read_i64()?);
+        }
+        let action = match action_id {
+            0 => CommandAction::Select,
+            1 => CommandAction::Move {
+                x: packet.read_f32()?,
+                y: packet.read_f32()?,
+            },
+            2 => CommandAction::Attack {
+                target: packet.read_i64()?,
+            },
+            3 => CommandAction::Build {
+                unit_type: packet.read_string()?,
+                x: packet.read_f32()?,
+                y: packet.read_f32()?,
+            },
+            4 => CommandAction::SetRally {
+                x: packet.read_f32()?,
+                y: packet.read_f32()?,
+            },
+            5 => CommandAction::Stop,
+            6 => CommandAction::Surrender,
+            _ => return Err(PacketError::InvalidPacketType),
+        };
+        Ok(Self {
+            slot,
+            units,
+            action,
+        })
+    }
+    pub fn write_body(&self, packet: &mut PacketWriter) -> Result<(), PacketError> {
+        packet.write_byte(self.slot)?;
+        packet.write_byte(self.action.id())?;
+        packet.write_i32(self.units.len() as i32)?;
+        for unit in &self.units {
+            packet.write_i64(*unit)?;
+        }
+        match &self.action {
+            CommandAction::Move { x, y } | CommandAction::SetRally { x, y } => {
+                packet.write_f32(*x)?;
+                packet.write_f32(*y)?;
+            }
+            CommandAction::Attack { target } => {
+                packet.write_i64(*target)?;
+            }
+            CommandAction::Build { unit_type, x, y } => {
+                packet.write_string(unit_type)?;
+                packet.write_f32(*x)?;
+                packet.write_f32(*y)?;
+            }
+            CommandAction::Select | CommandAction::Stop | CommandAction::Surrender => {}
+        }
+        Ok(())
+    }
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_ADD_GAME_COMMAND {
+            return Err(PacketError::InvalidPacketType);
+        }
+        Self::read_body(packet)
+    }
+}
+impl ToBytes for GameCommand {
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_ADD_GAME_COMMAND)?;
+        self.write_body(out)?;
+        out.finish_frame(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cmd = GameCommand::new(1, vec![7, 8], CommandAction::Move { x: 1.5, y: -2.0 });
+        let bytes = cmd.to_bytes().unwrap();
+        assert_eq!(GameCommand::from_packet(&mut Packet::new(bytes)).unwrap(), cmd);
+    }
+
+    #[test]
+    fn rejects_count_larger_than_body() {
+        let mut out = PacketWriter::new();
+        let frame = out.begin_frame(PACKET_ADD_GAME_COMMAND).unwrap();
+        out.write_byte(0).unwrap();
+        out.write_byte(0).unwrap();
+        out.write_i32(i32::MAX).unwrap();
+        out.write_i64(1).unwrap();
+        out.finish_frame(frame).unwrap();
+        let result = GameCommand::from_packet(&mut Packet::new(out.into_vec()));
+        assert!(matches!(result, Err(PacketError::OutOfBounds)));
+    }
+}
@@ -1,4 +1,3 @@
-use tokio::io::AsyncReadExt;
 use crate::error::PacketError;
 use crate::packet::Packet;
 pub const PACKET_HEART_BEAT: i32 = 108;
@@ -1,9 +1,7 @@
 
 use crate::error::PacketError;
 use crate::network::ToBytes;
-use crate::packet::Packet;
-use crate::protocol::preregister_connection::PACKET_PREREGISTER_CONNECTION;
-use chrono::prelude::*;
+use crate::packet::{Packet, PacketWriter};
 
 pub const PACKET_HEART_BEAT_RESPONSE: i32 = 109;
 #[derive(Debug, PartialEq)]
@@ -37,18 +35,11 @@ impl HeartBeatPacket {
     }
 }
 impl ToBytes for HeartBeatPacket {
-    fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
-        let mut inner = Packet::new();
-        inner.write_i32(PACKET_HEART_BEAT_RESPONSE)?;
-        inner.write_i64(self.ping_number)?;
-        inner.write_byte(self.unknown_byte)?;
-        inner.write_byte(self.unknown_byte2)?;
-
-        let mut final_packet = Packet::new();
-        let total_length = inner.payload.len() as i32 -4;
-        final_packet.write_i32(total_length)?;
-        final_packet.write_bytes(&inner.payload)?;
-
-        Ok(final_packet.payload)
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_HEART_BEAT_RESPONSE)?;
+        out.write_i64(self.ping_number)?;
+        out.write_byte(self.unknown_byte)?;
+        out.write_byte(self.unknown_byte2)?;
+        out.finish_frame(frame)
     }
 }
\ No newline at end of file
@@ -0,0 +1,61 @@
+//150 113 packet
+use crate::error::PacketError;
+use crate::packet::Packet;
+
+pub const PACKET_KICK: i32 = 150;
+// 服务器要密码但没给或者给错了 后面没有内容
+pub const PACKET_PASSWORD_ERROR: i32 = 113;
+// 踢出原因里出现这些单词就算封禁
+const BAN_WORDS: [&str; 3] = ["ban", "banned", "bans"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KickPacket {
+    pub reason: String,
+}
+impl KickPacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_KICK {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let reason = packet.read_string()?;
+        Ok(Self { reason })
+    }
+    // 服务器没有单独的封禁包 只能看踢出原因
+    // 按整个单词匹配 banana abandon之类的不算
+    pub fn is_ban(&self) -> bool {
+        self.reason
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| BAN_WORDS.iter().any(|ban| word.eq_ignore_ascii_case(ban)))
+    }
+    pub fn into_error(self) -> PacketError {
+        if self.is_ban() {
+            PacketError::Banned(self.reason)
+        } else {
+            PacketError::Kicked(self.reason)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kick(reason: &str) -> KickPacket {
+        KickPacket {
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn detects_bans_by_whole_word() {
+        assert!(kick("You are banned from this server").is_ban());
+        assert!(kick("BAN: cheating").is_ban());
+        assert!(kick("ip-ban").is_ban());
+        assert!(!kick("banana").is_ban());
+        assert!(!kick("Don't abandon your team").is_ban());
+        assert!(!kick("the band is playing").is_ban());
+        assert_eq!(kick("afk").into_error(), PacketError::Kicked("afk".to_string()));
+    }
+}
@@ -4,3 +4,13 @@ pub mod player_info;
 pub mod heart_beat;
 pub mod heart;
 
+pub mod game_command;
+pub mod tick;
+pub mod sync;
+pub mod team_list;
+pub mod server_info;
+pub mod start_game;
+pub mod chat;
+pub mod kick;
+pub mod disconnect;
+
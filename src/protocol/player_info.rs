@@ -1,10 +1,7 @@
-use sha2::digest::consts::False;
 //110 packet
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketWriter};
 use crate::error::PacketError;
 use crate::packet_utils::{compute_color_for_packet, compute_key_for_packet, compute_uuid_for_packet, SerKey};
-use crate::protocol::register_connection::PACKET_PREREGISTER_CONNECTION;
-use uuid::Uuid;
 use crate::network::ToBytes;
 
 pub const PACKET_PLAYER_INFO: i32 = 110;
@@ -25,24 +22,30 @@ pub struct PlayerInfoPacket {
     pub color: String,
 }
 impl PlayerInfoPacket {
-    pub fn new(packet: &mut Packet) -> Self {
+    // color为None时用服务器分配的颜色
+    // 161格式不对或UUID无效时返回错误
+    pub fn new(packet: &mut Packet, nickname: &str, client_uuid: &str, color: Option<i32>, spectator: bool) -> Result<Self, PacketError> {
         let mut a = SerKey::new();
-        SerKey::get(&mut a,packet);
-        let client_uuid = Uuid::new_v4().to_string();
-        Self {
+        SerKey::get(&mut a,packet)?;
+        Ok(Self {
             package_name: "com.corrodinggames.rts".to_string(),
             protocol_version: 5,
             game_version: 176,
             another_game_version:176,
-            nickname: "wanan".to_string(),
+            nickname: nickname.to_string(),
             is_password : false,
             password: String::new(),
             another_package_name: "com.corrodinggames.rts.java".to_string(),
-            uuid_sum : compute_uuid_for_packet(&*client_uuid, &*a.network_id),
+            uuid_sum : compute_uuid_for_packet(client_uuid, &a.network_id)?,
             client_units_checksum: 678359601,
             token : compute_key_for_packet(a.keys),
-            color: compute_color_for_packet(a.color),
-        }
+            // 观战者没有颜色
+            color: if spectator {
+                String::new()
+            } else {
+                compute_color_for_packet(color.unwrap_or(a.color))
+            },
+        })
     }
     pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
         let _total_length = packet.read_i32()?;
@@ -84,28 +87,22 @@ impl PlayerInfoPacket {
     }
 }
 impl ToBytes for PlayerInfoPacket {
-    fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
-        let mut inner = Packet::new();
-        inner.write_i32(PACKET_PLAYER_INFO)?;
-        inner.write_string(&self.package_name)?;
-        inner.write_i32(self.protocol_version)?;
-        inner.write_i32(self.game_version)?;
-        inner.write_i32(self.another_game_version)?;
-        inner.write_string(&self.nickname)?;
-        inner.write_bool(self.is_password)?;
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_PLAYER_INFO)?;
+        out.write_string(&self.package_name)?;
+        out.write_i32(self.protocol_version)?;
+        out.write_i32(self.game_version)?;
+        out.write_i32(self.another_game_version)?;
+        out.write_string(&self.nickname)?;
+        out.write_bool(self.is_password)?;
         if self.is_password {
-            inner.write_string(&self.password)?;
+            out.write_string(&self.password)?;
         };
-        inner.write_string(&self.another_package_name)?;
-        inner.write_string(&self.uuid_sum)?;
-        inner.write_i32(self.client_units_checksum)?;
-        inner.write_string(&self.token)?;
-        inner.write_string(&self.color)?;
-
-        let mut final_packet = Packet::new();
-        let total_length = inner.payload.len() as i32 - 4;
-        final_packet.write_i32(total_length)?;
-        final_packet.write_bytes(&inner.payload)?;
-        Ok(final_packet.payload)
+        out.write_string(&self.another_package_name)?;
+        out.write_string(&self.uuid_sum)?;
+        out.write_i32(self.client_units_checksum)?;
+        out.write_string(&self.token)?;
+        out.write_string(&self.color)?;
+        out.finish_frame(frame)
     }
 }
\ No newline at end of file
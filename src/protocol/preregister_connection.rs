@@ -1,4 +1,4 @@
-use crate::packet::Packet;
+use crate::packet::{Packet, PacketWriter};
 use crate::error::PacketError;
 use crate::network::ToBytes;
 
@@ -15,6 +15,11 @@ pub struct PreregisterConnectionPacket {
     pub locale: String,
 }
 
+impl Default for PreregisterConnectionPacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl PreregisterConnectionPacket {
     pub fn new() -> Self {
         Self {
@@ -69,30 +74,23 @@ impl PreregisterConnectionPacket {
 
 }
 impl ToBytes for PreregisterConnectionPacket {
-    fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
-        let mut inner = Packet::new();
-        inner.write_i32(PACKET_PREREGISTER_CONNECTION)?;
-        inner.write_string(&self.package_name)?;
-        inner.write_i32(self.protocol_version)?;
-        inner.write_i32(self.game_version)?;
-        inner.write_i32(self.another_game_version)?;
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_PREREGISTER_CONNECTION)?;
+        out.write_string(&self.package_name)?;
+        out.write_i32(self.protocol_version)?;
+        out.write_i32(self.game_version)?;
+        out.write_i32(self.another_game_version)?;
 
         if self.protocol_version >= 2 {
-            inner.write_is_string(&self.relay_id)?;
+            out.write_is_string(&self.relay_id)?;
         }
 
         if self.protocol_version >= 3 {
-            inner.write_string(&self.nickname)?;
+            out.write_string(&self.nickname)?;
         }
 
-        inner.write_string(&self.locale)?;
-        inner.write_i16(0)?;
-
-        let mut final_packet = Packet::new();
-        let total_length = inner.payload.len() as i32 -4;
-        final_packet.write_i32(total_length)?;
-        final_packet.write_bytes(&inner.payload)?;
-
-        Ok(final_packet.payload)
+        out.write_string(&self.locale)?;
+        out.write_i16(0)?;
+        out.finish_frame(frame)
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
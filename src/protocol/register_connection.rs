@@ -1,5 +1,6 @@
 use crate::error::PacketError;
-use crate::packet::Packet;
+use crate::network::ToBytes;
+use crate::packet::{Packet, PacketWriter};
 
 pub const PACKET_PREREGISTER_CONNECTION: i32 = 161;
 
@@ -15,6 +16,11 @@ pub struct RegisterConnectionPacket {
     pub color: i32,
     zero: i32,
 }
+impl Default for RegisterConnectionPacket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl RegisterConnectionPacket {
     pub fn new() -> Self {
         Self {
@@ -59,27 +65,21 @@ impl RegisterConnectionPacket {
             zero,
         })
     }
-    pub fn to_bytes(&self) -> Result<Vec<u8>, PacketError> {
-        let mut inner = Packet::new();
-        inner.write_i32(PACKET_PREREGISTER_CONNECTION)?;
-        inner.write_string(&self.server_id)?;
-        inner.write_i32(self.protocol_version)?;
-        inner.write_i32(self.game_version)?;
-        inner.write_i32(self.another_game_version)?;
-        inner.write_string(&self.pkg_name)?;
-        inner.write_string(&self.network_server_id)?;
-        inner.write_i32(self.server_key)?;
-        inner.write_i32(self.color)?;
-        inner.write_i32(0)?;
-
-        let mut final_packet = Packet::new();
-        let total_length = inner.payload.len() as i32 -4;
-        final_packet.write_i32(total_length)?;
-        final_packet.write_bytes(&inner.payload)?;
-
-        Ok(final_packet.payload)
+}
+impl ToBytes for RegisterConnectionPacket {
+    fn encode(&self, out: &mut PacketWriter) -> Result<(), PacketError> {
+        let frame = out.begin_frame(PACKET_PREREGISTER_CONNECTION)?;
+        out.write_string(&self.server_id)?;
+        out.write_i32(self.protocol_version)?;
+        out.write_i32(self.game_version)?;
+        out.write_i32(self.another_game_version)?;
+        out.write_string(&self.pkg_name)?;
+        out.write_string(&self.network_server_id)?;
+        out.write_i32(self.server_key)?;
+        out.write_i32(self.color)?;
+        out.write_i32(0)?;
+        out.finish_frame(frame)
     }
-
 }
 
 
@@ -0,0 +1,74 @@
+//106 packet
+use crate::error::PacketError;
+use crate::packet::{Packet, PacketWriter};
+
+pub const PACKET_SERVER_INFO: i32 = 106;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSettings {
+    pub map_name: String,
+    pub credits: i32,
+    pub fog: i32,
+    pub starting_units: i32,
+    pub income: f32,
+    pub ai_difficulty: i32,
+    pub no_nukes: bool,
+    pub shared_control: bool,
+}
+impl GameSettings {
+    pub fn read_body(packet: &mut Packet) -> Result<Self, PacketError> {
+        let map_name = packet.read_string()?;
+        let credits = packet.read_i32()?;
+        let fog = packet.read_i32()?;
+        let starting_units = packet.read_i32()?;
+        let income = packet.read_f32()?;
+        let ai_difficulty = packet.read_i32()?;
+        let no_nukes = packet.read_bool()?;
+        let shared_control = packet.read_bool()?;
+        Ok(Self {
+            map_name,
+            credits,
+            fog,
+            starting_units,
+            income,
+            ai_difficulty,
+            no_nukes,
+            shared_control,
+        })
+    }
+    pub fn write_body(&self, packet: &mut PacketWriter) -> Result<(), PacketError> {
+        packet.write_string(&self.map_name)?;
+        packet.write_i32(self.credits)?;
+        packet.write_i32(self.fog)?;
+        packet.write_i32(self.starting_units)?;
+        packet.write_f32(self.income)?;
+        packet.write_i32(self.ai_difficulty)?;
+        packet.write_bool(self.no_nukes)?;
+        packet.write_bool(self.shared_control)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfoPacket {
+    pub host_name: String,
+    pub game_version: i32,
+    pub settings: GameSettings,
+}
+impl ServerInfoPacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_SERVER_INFO {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let host_name = packet.read_string()?;
+        let game_version = packet.read_i32()?;
+        let settings = GameSettings::read_body(packet)?;
+        Ok(Self {
+            host_name,
+            game_version,
+            settings,
+        })
+    }
+}
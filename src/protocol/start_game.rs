@@ -0,0 +1,23 @@
+//120 packet
+use bytes::Bytes;
+use crate::error::PacketError;
+use crate::packet::Packet;
+
+pub const PACKET_START_GAME: i32 = 120;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartGamePacket {
+    // 地图和存档数据 目前不解析
+    pub data: Bytes,
+}
+impl StartGamePacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_START_GAME {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let data = packet.read_bytes(total_length.max(0) as usize)?;
+        Ok(Self { data })
+    }
+}
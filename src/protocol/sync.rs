@@ -0,0 +1,25 @@
+//35 packet
+use bytes::Bytes;
+use crate::error::PacketError;
+use crate::packet::Packet;
+
+pub const PACKET_SYNC: i32 = 35;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncPacket {
+    pub tick: i32,
+    // 存档数据 目前不解析
+    pub data: Bytes,
+}
+impl SyncPacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_SYNC {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let tick = packet.read_i32()?;
+        let data = packet.read_bytes((total_length as usize).saturating_sub(4))?;
+        Ok(Self { tick, data })
+    }
+}
@@ -0,0 +1,69 @@
+//115 packet
+use crate::error::PacketError;
+use crate::packet::Packet;
+
+pub const PACKET_TEAM_LIST: i32 = 115;
+// 观战者的队伍编号 队伍列表和换队命令里都用它 观战者没有单独的位置编号
+pub const SPECTATOR_TEAM: i32 = -3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamListEntry {
+    pub slot: i32,
+    pub team: i32,
+    pub nickname: String,
+    pub color: i32,
+    pub ready: bool,
+    pub is_host: bool,
+    pub is_admin: bool,
+    pub ping: i32,
+}
+impl TeamListEntry {
+    fn read_body(packet: &mut Packet, slot: i32) -> Result<Self, PacketError> {
+        let team = packet.read_i32()?;
+        let nickname = packet.read_string()?;
+        let color = packet.read_i32()?;
+        let ready = packet.read_bool()?;
+        let is_host = packet.read_bool()?;
+        let is_admin = packet.read_bool()?;
+        let ping = packet.read_i32()?;
+        Ok(Self {
+            slot,
+            team,
+            nickname,
+            color,
+            ready,
+            is_host,
+            is_admin,
+            ping,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamListPacket {
+    // 自己所在的位置
+    pub my_slot: i32,
+    pub players: Vec<TeamListEntry>,
+}
+impl TeamListPacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_TEAM_LIST {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let my_slot = packet.read_i32()?;
+        let slot_count = packet.read_i32()?;
+        let mut players = Vec::new();
+        // 空位只有一个false
+        for slot in 0..slot_count {
+            if packet.read_bool()? {
+                players.push(TeamListEntry::read_body(packet, slot)?);
+            }
+        }
+        Ok(Self { my_slot, players })
+    }
+    pub fn me(&self) -> Option<&TeamListEntry> {
+        self.players.iter().find(|p| p.slot == self.my_slot)
+    }
+}
@@ -0,0 +1,66 @@
+//10 packet
+use crate::error::PacketError;
+use crate::packet::Packet;
+use crate::protocol::game_command::GameCommand;
+
+pub const PACKET_TICK: i32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TickPacket {
+    pub tick: i32,
+    pub commands: Vec<GameCommand>,
+}
+impl TickPacket {
+    pub fn from_packet(packet: &mut Packet) -> Result<Self, PacketError> {
+        let _total_length = packet.read_i32()?;
+        let packet_type = packet.read_i32()?;
+        if packet_type != PACKET_TICK {
+            return Err(PacketError::InvalidPacketType);
+        }
+        let tick = packet.read_i32()?;
+        let command_count = packet.read_count(GameCommand::MIN_BODY_LEN)?;
+        let mut commands = Vec::with_capacity(command_count);
+        for _ in 0..command_count {
+            commands.push(GameCommand::read_body(packet)?);
+        }
+        Ok(Self { tick, commands })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::PacketWriter;
+    use crate::protocol::game_command::CommandAction;
+
+    fn tick_frame(tick: i32, count: i32, commands: &[GameCommand]) -> Vec<u8> {
+        let mut out = PacketWriter::new();
+        let frame = out.begin_frame(PACKET_TICK).unwrap();
+        out.write_i32(tick).unwrap();
+        out.write_i32(count).unwrap();
+        for cmd in commands {
+            cmd.write_body(&mut out).unwrap();
+        }
+        out.finish_frame(frame).unwrap();
+        out.into_vec()
+    }
+
+    #[test]
+    fn reads_commands() {
+        let commands = vec![
+            GameCommand::new(0, vec![1], CommandAction::Stop),
+            GameCommand::new(1, vec![], CommandAction::Attack { target: 9 }),
+        ];
+        let packet = TickPacket::from_packet(&mut Packet::new(tick_frame(42, 2, &commands))).unwrap();
+        assert_eq!(packet.tick, 42);
+        assert_eq!(packet.commands, commands);
+    }
+
+    #[test]
+    fn rejects_bad_counts() {
+        for count in [-1, i32::MAX] {
+            let result = TickPacket::from_packet(&mut Packet::new(tick_frame(1, count, &[])));
+            assert!(matches!(result, Err(PacketError::OutOfBounds)));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+use rand::Rng;
+
+// 断线后自动重连 每次等待时间翻倍 再加上随机抖动 避免大量机器人同时重连
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    // 连续失败这么多次后放弃 成功注册后重新计数
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    // 0到1 等待时间随机减少最多这个比例
+    pub jitter: f64,
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+impl ReconnectPolicy {
+    // attempt从1开始
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = base.min(self.max_delay.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+        Duration::from_secs_f64(capped * (1.0 - jitter))
+    }
+}
@@ -0,0 +1,425 @@
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+use tokio::time::Instant;
+use crate::client::{FakePlayer, FakePlayerBuilder};
+use crate::error::PacketError;
+use crate::event::ClientEvent;
+use crate::protocol::chat::ChatReceivePacket;
+use crate::protocol::team_list::TeamListPacket;
+use crate::shutdown::SHUTDOWN_DEADLINE;
+use crate::transport::{Connector, TcpConnector};
+
+fn default_timeout_ms() -> u64 {
+    5000
+}
+fn default_bot() -> usize {
+    1
+}
+
+// 机器人编号从1开始
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    Connect {
+        bots: usize,
+    },
+    ExpectRoster {
+        players: usize,
+        #[serde(default = "default_bot")]
+        bot: usize,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    Chat {
+        bot: usize,
+        message: String,
+    },
+    // 不写bots时除了from以外的所有机器人都要收到
+    ExpectChat {
+        message: String,
+        from: Option<usize>,
+        bots: Option<Vec<usize>>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    // 修改类的步骤等服务器确认 超过timeout_ms算失败
+    SetTeam {
+        bot: usize,
+        team: i32,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    SetReady {
+        bot: usize,
+        ready: bool,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    StartGame {
+        bot: usize,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    ExpectStart {
+        bots: Option<Vec<usize>>,
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+    Wait {
+        ms: u64,
+    },
+    Leave {
+        bot: usize,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub server: String,
+    // 第i个机器人的昵称是 nickname+i
+    #[serde(default = "default_nickname")]
+    pub nickname: String,
+    pub steps: Vec<Step>,
+}
+fn default_nickname() -> String {
+    "qa".to_string()
+}
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Passed,
+    Failed(String),
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct StepReport {
+    pub index: usize,
+    pub step: Step,
+    pub status: StepStatus,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub steps: Vec<StepReport>,
+}
+impl ScenarioReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|s| s.status == StepStatus::Passed)
+    }
+}
+
+struct Bot {
+    player: FakePlayer,
+    roster: Option<TeamListPacket>,
+    chats: Vec<ChatReceivePacket>,
+    started: bool,
+    closed: bool,
+}
+impl Bot {
+    fn observe(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::RosterUpdated(list) => self.roster = Some(list),
+            ClientEvent::Chat(chat) => self.chats.push(chat),
+            ClientEvent::GameStarted => self.started = true,
+            ClientEvent::Disconnected { .. } => self.closed = true,
+            _ => {}
+        }
+    }
+    // 一直处理事件直到条件满足或超时
+    async fn wait_for(&mut self, deadline: Instant, mut done: impl FnMut(&mut Bot) -> bool) -> bool {
+        loop {
+            if done(self) {
+                return true;
+            }
+            if self.closed {
+                return false;
+            }
+            match tokio::time::timeout_at(deadline, self.player.next_event()).await {
+                Ok(Some(event)) => self.observe(event),
+                Ok(None) => self.closed = true,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+// 默认直连scenario.server 测试时可以换成内存连接
+pub struct ScenarioRunner<C = TcpConnector> {
+    scenario: Scenario,
+    bots: Vec<Bot>,
+    connector: C,
+}
+impl ScenarioRunner {
+    pub fn new(scenario: Scenario) -> Self {
+        Self {
+            scenario,
+            bots: Vec::new(),
+            connector: TcpConnector,
+        }
+    }
+}
+impl<C: Connector + Clone> ScenarioRunner<C> {
+    pub fn connector<D: Connector + Clone>(self, connector: D) -> ScenarioRunner<D> {
+        ScenarioRunner {
+            scenario: self.scenario,
+            bots: self.bots,
+            connector,
+        }
+    }
+    // 第一个失败的步骤之后全部跳过
+    pub async fn run(mut self) -> ScenarioReport {
+        let mut steps = Vec::new();
+        let mut failed = false;
+        for (index, step) in self.scenario.steps.clone().into_iter().enumerate() {
+            if failed {
+                steps.push(StepReport {
+                    index,
+                    step,
+                    status: StepStatus::Skipped,
+                    elapsed: Duration::ZERO,
+                });
+                continue;
+            }
+            let started = Instant::now();
+            let status = match self.run_step(&step).await {
+                Ok(()) => StepStatus::Passed,
+                Err(e) => {
+                    failed = true;
+                    StepStatus::Failed(e)
+                }
+            };
+            steps.push(StepReport {
+                index,
+                step,
+                status,
+                elapsed: started.elapsed(),
+            });
+        }
+        // 等离开包发出去再结束 不然最后的步骤可能还没到服务器
+        for (index, bot) in self.bots.iter().enumerate() {
+            match tokio::time::timeout(SHUTDOWN_DEADLINE, bot.player.disconnect()).await {
+                Ok(Ok(())) => {}
+                // 已经离开或被断开的机器人
+                Ok(Err(_)) if bot.closed => {}
+                Ok(Err(e)) => log::warn!("机器人{}断开失败:{}", index + 1, e),
+                Err(_) => log::warn!("机器人{}在{:?}内没有断开", index + 1, SHUTDOWN_DEADLINE),
+            }
+        }
+        ScenarioReport {
+            name: self.scenario.name.clone(),
+            steps,
+        }
+    }
+    fn bot(&mut self, bot: usize) -> Result<&mut Bot, String> {
+        let count = self.bots.len();
+        bot.checked_sub(1)
+            .and_then(|i| self.bots.get_mut(i))
+            .ok_or_else(|| format!("没有第{}个机器人 当前共{}个", bot, count))
+    }
+    fn targets(&self, bots: &Option<Vec<usize>>, except: Option<usize>) -> Vec<usize> {
+        match bots {
+            Some(bots) => bots.clone(),
+            None => (1..=self.bots.len()).filter(|b| Some(*b) != except).collect(),
+        }
+    }
+    async fn run_step(&mut self, step: &Step) -> Result<(), String> {
+        match step {
+            Step::Connect { bots } => {
+                for _ in 0..*bots {
+                    let nickname = format!("{}{}", self.scenario.nickname, self.bots.len() + 1);
+                    let player = FakePlayerBuilder::new(&self.scenario.server)
+                        .connector(self.connector.clone())
+                        .nickname(&nickname)
+                        .connect()
+                        .await
+                        .map_err(|e| format!("{}连接失败:{}", nickname, e))?;
+                    self.bots.push(Bot {
+                        player,
+                        roster: None,
+                        chats: Vec::new(),
+                        started: false,
+                        closed: false,
+                    });
+                }
+                Ok(())
+            }
+            Step::ExpectRoster { players, bot, timeout_ms } => {
+                let deadline = Instant::now() + Duration::from_millis(*timeout_ms);
+                let bot = self.bot(*bot)?;
+                let ok = bot
+                    .wait_for(deadline, |b| b.roster.as_ref().is_some_and(|r| r.players.len() == *players))
+                    .await;
+                if ok {
+                    Ok(())
+                } else {
+                    let seen = bot.roster.as_ref().map_or(0, |r| r.players.len());
+                    Err(format!("期望{}个玩家 实际{}个", players, seen))
+                }
+            }
+            Step::Chat { bot, message } => self
+                .bot(*bot)?
+                .player
+                .send_chat(message)
+                .map_err(|e| e.to_string()),
+            Step::ExpectChat { message, from, bots, timeout_ms } => {
+                let deadline = Instant::now() + Duration::from_millis(*timeout_ms);
+                let mut missing = Vec::new();
+                for target in self.targets(bots, *from) {
+                    let received = self
+                        .bot(target)?
+                        .wait_for(deadline, |b| {
+                            match b.chats.iter().position(|c| c.message == *message) {
+                                Some(i) => {
+                                    b.chats.remove(i);
+                                    true
+                                }
+                                None => false,
+                            }
+                        })
+                        .await;
+                    if !received {
+                        missing.push(target);
+                    }
+                }
+                if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("机器人{:?}没有收到\"{}\"", missing, message))
+                }
+            }
+            Step::SetTeam { bot, team, timeout_ms } => {
+                let player = &self.bot(*bot)?.player;
+                within(*timeout_ms, "换队伍", player.set_team(*team)).await
+            }
+            Step::SetReady { bot, ready, timeout_ms } => {
+                let player = &self.bot(*bot)?.player;
+                within(*timeout_ms, "准备", player.set_ready(*ready)).await
+            }
+            Step::StartGame { bot, timeout_ms } => {
+                let player = &self.bot(*bot)?.player;
+                within(*timeout_ms, "开始游戏", player.start_game()).await
+            }
+            Step::ExpectStart { bots, timeout_ms } => {
+                let deadline = Instant::now() + Duration::from_millis(*timeout_ms);
+                let mut missing = Vec::new();
+                for target in self.targets(bots, None) {
+                    if !self.bot(target)?.wait_for(deadline, |b| b.started).await {
+                        missing.push(target);
+                    }
+                }
+                if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("机器人{:?}没有收到开始游戏", missing))
+                }
+            }
+            Step::Wait { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+                Ok(())
+            }
+            Step::Leave { bot } => self.bot(*bot)?.player.leave().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+async fn within(timeout_ms: u64, what: &str, change: impl Future<Output = Result<(), PacketError>>) -> Result<(), String> {
+    match tokio::time::timeout(Duration::from_millis(timeout_ms), change).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("{}ms内没有完成{}", timeout_ms, what)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use super::*;
+    use crate::packet::frame;
+    use crate::protocol::team_list::PACKET_TEAM_LIST;
+    use crate::transport::memory;
+
+    const SCENARIO: &str = r#"
+name = "内存服务器"
+server = "memory"
+
+[[steps]]
+action = "connect"
+bots = 1
+
+[[steps]]
+action = "expect_roster"
+players = 1
+timeout_ms = 1000
+
+[[steps]]
+action = "chat"
+bot = 1
+message = "hi"
+
+[[steps]]
+action = "set_team"
+bot = 1
+team = 1
+timeout_ms = 100
+
+[[steps]]
+action = "expect_start"
+"#;
+
+    // 只有机器人自己在0号位置的队伍列表
+    fn roster() -> Vec<u8> {
+        frame(PACKET_TEAM_LIST, |out| {
+            out.write_i32(0).unwrap();
+            out.write_i32(1).unwrap();
+            out.write_bool(true).unwrap();
+            out.write_i32(0).unwrap();
+            out.write_string("qa1").unwrap();
+            out.write_i32(0).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_bool(false).unwrap();
+            out.write_i32(0).unwrap();
+        })
+    }
+
+    #[test]
+    fn parses_steps_with_default_timeouts() {
+        let scenario: Scenario = toml::from_str(SCENARIO).unwrap();
+        assert_eq!(scenario.nickname, "qa");
+        assert_eq!(scenario.steps.len(), 5);
+        assert!(matches!(scenario.steps[3], Step::SetTeam { bot: 1, team: 1, timeout_ms: 100 }));
+        assert!(matches!(scenario.steps[4], Step::ExpectStart { bots: None, timeout_ms: 5000 }));
+    }
+
+    #[tokio::test]
+    async fn stops_at_the_first_unconfirmed_change() {
+        let (connector, mut listener) = memory();
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap();
+            stream.write_all(&roster()).await.unwrap();
+            // 只收不回 换队伍永远等不到确认
+            let mut received = Vec::new();
+            let _ = stream.read_to_end(&mut received).await;
+        });
+        let scenario: Scenario = toml::from_str(SCENARIO).unwrap();
+        let report = ScenarioRunner::new(scenario).connector(connector).run().await;
+        let statuses: Vec<_> = report.steps.iter().map(|s| &s.status).collect();
+        assert_eq!(statuses[..3], [&StepStatus::Passed; 3]);
+        assert!(matches!(statuses[3], StepStatus::Failed(e) if e.contains("换队伍")));
+        assert_eq!(statuses[4], &StepStatus::Skipped);
+        assert!(!report.passed());
+        // 结束时机器人断开 服务器端读到结尾
+        server.await.unwrap();
+    }
+}
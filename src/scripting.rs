@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use rhai::{Array, CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+use crate::client::{recv_event, FakePlayer};
+use crate::error::PacketError;
+use crate::event::{ClientEvent, DisconnectReason};
+use crate::protocol::team_list::TeamListPacket;
+
+pub const SCRIPT_EXTENSION: &str = "rhai";
+// 每隔这么久检查一次脚本目录
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+// 一次回调或加载最多执行这么多步 死循环的脚本会报错而不是卡住机器人
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+pub const MAX_CALL_LEVELS: usize = 32;
+pub const MAX_STRING_SIZE: usize = 64 * 1024;
+pub const MAX_COLLECTION_SIZE: usize = 10_000;
+
+// 脚本里调用的操作先排队 回调结束后再由客户端执行
+#[derive(Debug, Clone, PartialEq)]
+enum ScriptAction {
+    Chat(String),
+    Team(i32),
+    Ready(bool),
+    Leave,
+}
+
+struct Script {
+    modified: SystemTime,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+// 脚本可以定义这些函数 没定义的不会调用
+// on_chat(sender, message, slot) on_roster(players) on_game_start() on_disconnect(reason)
+pub struct ScriptHost {
+    dir: PathBuf,
+    // 加载在阻塞线程里做 要共享
+    engine: Arc<Engine>,
+    scripts: BTreeMap<PathBuf, Script>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+}
+impl ScriptHost {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        engine.set_max_string_size(MAX_STRING_SIZE);
+        engine.set_max_array_size(MAX_COLLECTION_SIZE);
+        engine.set_max_map_size(MAX_COLLECTION_SIZE);
+        let queue = actions.clone();
+        engine.register_fn("send_chat", move |message: &str| {
+            queue.lock().unwrap().push(ScriptAction::Chat(message.to_string()));
+        });
+        let queue = actions.clone();
+        engine.register_fn("set_team", move |team: i64| {
+            queue.lock().unwrap().push(ScriptAction::Team(team as i32));
+        });
+        let queue = actions.clone();
+        engine.register_fn("set_ready", move |ready: bool| {
+            queue.lock().unwrap().push(ScriptAction::Ready(ready));
+        });
+        let queue = actions.clone();
+        engine.register_fn("leave", move || {
+            queue.lock().unwrap().push(ScriptAction::Leave);
+        });
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            engine: Arc::new(engine),
+            scripts: BTreeMap::new(),
+            actions,
+        }
+    }
+    // 加载新增和修改过的脚本 删掉已经不存在的
+    // 读文件 编译和顶层代码都在阻塞线程里跑 不占用运行时
+    pub async fn reload(&mut self) {
+        let known: BTreeMap<PathBuf, SystemTime> = self
+            .scripts
+            .iter()
+            .map(|(path, script)| (path.clone(), script.modified))
+            .collect();
+        let engine = self.engine.clone();
+        let dir = self.dir.clone();
+        let scanned = tokio::task::spawn_blocking(move || scan(&engine, &dir, &known)).await;
+        let (found, loaded) = match scanned {
+            Ok(Ok(scanned)) => scanned,
+            Ok(Err(e)) => {
+                log::warn!("读取脚本目录失败:{}", e);
+                return;
+            }
+            Err(e) => {
+                log::warn!("加载脚本失败:{}", e);
+                return;
+            }
+        };
+        for (path, result) in loaded {
+            match result {
+                Ok(script) => {
+                    log::info!("加载脚本:{}", path.display());
+                    self.scripts.insert(path, script);
+                }
+                Err(e) => log::warn!("脚本{}加载失败:{}", path.display(), e),
+            }
+        }
+        self.scripts.retain(|path, _| found.contains(path));
+    }
+    fn call(&mut self, name: &str, args: impl FuncArgs + Clone) {
+        for (path, script) in self.scripts.iter_mut() {
+            if !script.ast.iter_functions().any(|f| f.name == name) {
+                continue;
+            }
+            // 顶层代码只在加载时跑一次 回调里改的变量留在scope里
+            let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut script.scope,
+                &script.ast,
+                name,
+                args.clone(),
+            );
+            if let Err(e) = result {
+                log::warn!("脚本{}的{}出错:{}", path.display(), name, e);
+            }
+        }
+    }
+    fn dispatch(&mut self, event: &ClientEvent) {
+        match event {
+            ClientEvent::Chat(chat) => self.call(
+                "on_chat",
+                (chat.sender.clone(), chat.message.clone(), chat.slot as i64),
+            ),
+            ClientEvent::RosterUpdated(list) => self.call("on_roster", (roster_to_array(list),)),
+            ClientEvent::GameStarted => self.call("on_game_start", ()),
+            ClientEvent::Disconnected { reason } => {
+                let reason = match reason {
+                    DisconnectReason::Left => "left".to_string(),
+                    DisconnectReason::Closed => "closed".to_string(),
+                    DisconnectReason::Timeout(t) => format!("{} timeout", t),
+                    DisconnectReason::Kicked(r) => format!("kicked: {}", r),
+                    DisconnectReason::Banned(r) => format!("banned: {}", r),
+                    DisconnectReason::WrongPassword => "wrong password".to_string(),
+                    DisconnectReason::Error(e) => e.clone(),
+                };
+                self.call("on_disconnect", (reason,))
+            }
+            _ => {}
+        }
+    }
+    fn take_actions(&self) -> Vec<ScriptAction> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+}
+
+type ActionFuture<'a> = Pin<Box<dyn Future<Output = Result<(), PacketError>> + Send + 'a>>;
+type Scanned = (Vec<PathBuf>, Vec<(PathBuf, Result<Script, String>)>);
+
+// 返回目录里所有脚本 和新增或修改过的脚本的加载结果
+fn scan(engine: &Engine, dir: &Path, known: &BTreeMap<PathBuf, SystemTime>) -> std::io::Result<Scanned> {
+    let mut found = Vec::new();
+    let mut loaded = Vec::new();
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != SCRIPT_EXTENSION) {
+            continue;
+        }
+        let modified = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        found.push(path.clone());
+        if known.get(&path) == Some(&modified) {
+            continue;
+        }
+        let script = load(engine, &path, modified);
+        loaded.push((path, script));
+    }
+    Ok((found, loaded))
+}
+
+fn load(engine: &Engine, path: &Path, modified: SystemTime) -> Result<Script, String> {
+    let ast = engine
+        .compile_file(path.to_path_buf())
+        .map_err(|e| e.to_string())?;
+    // 先跑一遍顶层代码 让脚本初始化自己的变量
+    let mut scope = Scope::new();
+    engine
+        .run_ast_with_scope(&mut scope, &ast)
+        .map_err(|e| e.to_string())?;
+    Ok(Script {
+        modified,
+        ast,
+        scope,
+    })
+}
+
+fn roster_to_array(list: &TeamListPacket) -> Array {
+    list.players
+        .iter()
+        .map(|p| {
+            let mut player = Map::new();
+            player.insert("slot".into(), (p.slot as i64).into());
+            player.insert("team".into(), (p.team as i64).into());
+            player.insert("nickname".into(), p.nickname.clone().into());
+            player.insert("ready".into(), p.ready.into());
+            player.insert("is_host".into(), p.is_host.into());
+            player.insert("is_me".into(), (p.slot == list.my_slot).into());
+            player.into()
+        })
+        .collect()
+}
+
+impl FakePlayer {
+    // 运行目录里的脚本 文件改动后自动重新加载 直到连接关闭
+    // 换队伍和准备要等服务器确认 一个一个在后台等 不耽误处理后面的事件
+    pub async fn run_scripts(&self, mut host: ScriptHost) -> Result<(), PacketError> {
+        let mut events = self.subscribe();
+        let mut reload = tokio::time::interval(RELOAD_INTERVAL);
+        let mut queued: VecDeque<ScriptAction> = VecDeque::new();
+        let mut running: Option<ActionFuture<'_>> = None;
+        loop {
+            if running.is_none() {
+                running = match queued.pop_front() {
+                    Some(ScriptAction::Team(team)) => Some(Box::pin(self.set_team(team))),
+                    Some(ScriptAction::Ready(ready)) => Some(Box::pin(self.set_ready(ready))),
+                    _ => None,
+                };
+            }
+            tokio::select! {
+                _ = reload.tick() => host.reload().await,
+                result = async { running.as_mut().unwrap().await }, if running.is_some() => {
+                    running = None;
+                    if let Err(e) = result {
+                        log::warn!("脚本操作失败:{}", e);
+                    }
+                }
+                event = recv_event(&mut events) => {
+                    let Some(event) = event else { return Ok(()) };
+                    host.dispatch(&event);
+                    for action in host.take_actions() {
+                        let result = match action {
+                            ScriptAction::Chat(message) => self.send_chat(&message),
+                            ScriptAction::Leave => self.leave(),
+                            action => {
+                                queued.push_back(action);
+                                Ok(())
+                            }
+                        };
+                        if let Err(e) = result {
+                            log::warn!("脚本操作失败:{}", e);
+                        }
+                    }
+                    if let ClientEvent::Disconnected { .. } = event {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::chat::ChatReceivePacket;
+
+    fn script_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rwnew-scripts-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn calls_callbacks_and_queues_actions() {
+        let dir = script_dir(
+            "chat",
+            &[
+                ("greet.rhai", r#"let count = 0; fn on_chat(sender, message, slot) { send_chat("hi " + sender); set_team(slot); }"#),
+                ("notes.txt", "not a script"),
+            ],
+        );
+        let mut host = ScriptHost::new(&dir);
+        host.reload().await;
+        assert_eq!(host.scripts.len(), 1);
+        host.dispatch(&ClientEvent::Chat(ChatReceivePacket {
+            message: "hello".to_string(),
+            sender: "a".to_string(),
+            slot: 2,
+        }));
+        assert_eq!(
+            host.take_actions(),
+            vec![ScriptAction::Chat("hi a".to_string()), ScriptAction::Team(2)]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn top_level_code_runs_only_at_load() {
+        let dir = script_dir(
+            "once",
+            &[("once.rhai", r#"send_chat("loaded"); fn on_game_start() { send_chat("started"); }"#)],
+        );
+        let mut host = ScriptHost::new(&dir);
+        host.reload().await;
+        assert_eq!(host.take_actions(), vec![ScriptAction::Chat("loaded".to_string())]);
+        host.dispatch(&ClientEvent::GameStarted);
+        host.dispatch(&ClientEvent::GameStarted);
+        assert_eq!(
+            host.take_actions(),
+            vec![ScriptAction::Chat("started".to_string()), ScriptAction::Chat("started".to_string())]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn runaway_scripts_fail_to_load() {
+        let dir = script_dir(
+            "limits",
+            &[
+                ("spin.rhai", "loop {}"),
+                ("deep.rhai", "fn f(n) { f(n + 1) } f(0);"),
+                ("big.rhai", r#"let s = "x"; loop { s += s; }"#),
+            ],
+        );
+        let mut host = ScriptHost::new(&dir);
+        host.reload().await;
+        assert!(host.scripts.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
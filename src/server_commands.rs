@@ -0,0 +1,155 @@
+use crate::connection::LobbyChange;
+use crate::host::HostAction;
+use crate::protocol::server_info::GameSettings;
+
+// 大厅修改和房主操作都通过服务器的聊天命令完成 协议里没有能对照的包
+// 不同服务器的命令名可能不一样 连接前在PlayerOptions里改
+// 位置和队伍编号和队伍列表里的一样
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerCommands {
+    pub prefix: String,
+    pub team: String,
+    pub color: String,
+    pub ready: String,
+    pub unready: String,
+    pub map: String,
+    pub move_player: String,
+    pub kick: String,
+    pub start: String,
+    pub credits: String,
+    pub fog: String,
+    pub starting_units: String,
+    pub income: String,
+    pub ai_difficulty: String,
+    pub nukes: String,
+    pub shared_control: String,
+}
+impl ServerCommands {
+    pub fn new() -> Self {
+        Self {
+            prefix: ".".to_string(),
+            team: "self_team".to_string(),
+            color: "self_color".to_string(),
+            ready: "ready".to_string(),
+            unready: "unready".to_string(),
+            map: "map".to_string(),
+            move_player: "move".to_string(),
+            kick: "kick".to_string(),
+            start: "start".to_string(),
+            credits: "credits".to_string(),
+            fog: "fog".to_string(),
+            starting_units: "startingunits".to_string(),
+            income: "income".to_string(),
+            ai_difficulty: "ai".to_string(),
+            nukes: "nukes".to_string(),
+            shared_control: "sharedcontrol".to_string(),
+        }
+    }
+    // 发给服务器的聊天内容
+    pub fn lobby(&self, change: &LobbyChange) -> String {
+        match change {
+            LobbyChange::Team(team) => self.command(&self.team, &[&team.to_string()]),
+            LobbyChange::Color(color) => self.command(&self.color, &[&color.to_string()]),
+            LobbyChange::Ready(true) => self.command(&self.ready, &[]),
+            LobbyChange::Ready(false) => self.command(&self.unready, &[]),
+        }
+    }
+    // 一个操作可能要发好几条
+    pub fn host(&self, action: &HostAction) -> Vec<String> {
+        match action {
+            HostAction::ChangeMap(map_name) => vec![self.command(&self.map, &[map_name])],
+            HostAction::ChangeSettings(settings) => self.settings(settings),
+            HostAction::MovePlayer { slot, team } => {
+                vec![self.command(&self.move_player, &[&slot.to_string(), &team.to_string()])]
+            }
+            HostAction::Kick { slot, reason } => {
+                // 踢人命令不带原因 先把原因发到聊天里
+                let mut lines = Vec::new();
+                if !reason.is_empty() {
+                    lines.push(reason.clone());
+                }
+                lines.push(self.command(&self.kick, &[&slot.to_string()]));
+                lines
+            }
+            HostAction::StartGame => vec![self.command(&self.start, &[])],
+        }
+    }
+    fn settings(&self, settings: &GameSettings) -> Vec<String> {
+        vec![
+            self.command(&self.map, &[&settings.map_name]),
+            self.command(&self.credits, &[&settings.credits.to_string()]),
+            self.command(&self.fog, &[&settings.fog.to_string()]),
+            self.command(&self.starting_units, &[&settings.starting_units.to_string()]),
+            self.command(&self.income, &[&settings.income.to_string()]),
+            self.command(&self.ai_difficulty, &[&settings.ai_difficulty.to_string()]),
+            self.command(&self.nukes, &[&(!settings.no_nukes).to_string()]),
+            self.command(&self.shared_control, &[&settings.shared_control.to_string()]),
+        ]
+    }
+    fn command(&self, name: &str, args: &[&str]) -> String {
+        let mut line = format!("{}{}", self.prefix, name);
+        for arg in args {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        line
+    }
+}
+impl Default for ServerCommands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_lobby_commands() {
+        let mut commands = ServerCommands::new();
+        assert_eq!(commands.lobby(&LobbyChange::Team(2)), ".self_team 2");
+        assert_eq!(commands.lobby(&LobbyChange::Color(5)), ".self_color 5");
+        assert_eq!(commands.lobby(&LobbyChange::Ready(true)), ".ready");
+        assert_eq!(commands.lobby(&LobbyChange::Ready(false)), ".unready");
+        commands.prefix = "-".to_string();
+        commands.team = "team".to_string();
+        assert_eq!(commands.lobby(&LobbyChange::Team(-3)), "-team -3");
+    }
+
+    #[test]
+    fn formats_host_commands() {
+        let commands = ServerCommands::new();
+        assert_eq!(commands.host(&HostAction::ChangeMap("Crossing Large".into())), vec![".map Crossing Large"]);
+        assert_eq!(commands.host(&HostAction::MovePlayer { slot: 1, team: 2 }), vec![".move 1 2"]);
+        assert_eq!(
+            commands.host(&HostAction::Kick { slot: 3, reason: "bye".into() }),
+            vec!["bye", ".kick 3"]
+        );
+        assert_eq!(commands.host(&HostAction::Kick { slot: 3, reason: "".into() }), vec![".kick 3"]);
+        assert_eq!(commands.host(&HostAction::StartGame), vec![".start"]);
+        let settings = GameSettings {
+            map_name: "map".into(),
+            credits: 4000,
+            fog: 1,
+            starting_units: 2,
+            income: 1.5,
+            ai_difficulty: 3,
+            no_nukes: true,
+            shared_control: false,
+        };
+        assert_eq!(
+            commands.host(&HostAction::ChangeSettings(settings)),
+            vec![
+                ".map map",
+                ".credits 4000",
+                ".fog 1",
+                ".startingunits 2",
+                ".income 1.5",
+                ".ai 3",
+                ".nukes false",
+                ".sharedcontrol false",
+            ]
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use crate::client::FakePlayerBuilder;
+use crate::event::{ClientEvent, DisconnectReason};
+use crate::loadtest::{failure_kind, BotTiming, Histogram, LoadReport};
+use crate::reconnect::ReconnectPolicy;
+
+// 每个机器人的事件通道 只有run_bot一个订阅者 不需要很大
+pub const SWARM_EVENT_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct SwarmOptions {
+    pub address: String,
+    // {} 会替换成机器人编号 从1开始
+    pub nickname_template: String,
+    pub count: usize,
+    // 每两个机器人之间的连接间隔
+    pub connect_interval: Duration,
+    // 轮流分配给每个机器人 为空时不绑定
+    pub local_addrs: Vec<IpAddr>,
+    pub reconnect: Option<ReconnectPolicy>,
+}
+impl SwarmOptions {
+    pub fn new(address: &str, count: usize) -> Self {
+        Self {
+            address: address.to_string(),
+            nickname_template: "bot{}".to_string(),
+            count,
+            connect_interval: Duration::from_millis(100),
+            local_addrs: Vec::new(),
+            reconnect: None,
+        }
+    }
+    pub fn nickname(&self, index: usize) -> String {
+        self.nickname_template.replace("{}", &(index + 1).to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotState {
+    Waiting,
+    Connecting,
+    Connected,
+    InGame,
+    Failed(String),
+    Disconnected(DisconnectReason),
+}
+impl BotState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BotState::Waiting => "waiting",
+            BotState::Connecting => "connecting",
+            BotState::Connected => "connected",
+            BotState::InGame => "in_game",
+            BotState::Failed(_) => "failed",
+            BotState::Disconnected(_) => "disconnected",
+        }
+    }
+}
+
+// 所有机器人共用调用者的tokio运行时 每个机器人一个任务
+pub struct Swarm {
+    states: Arc<Mutex<Vec<BotState>>>,
+    timings: Arc<Mutex<Vec<BotTiming>>>,
+    // 所有机器人共用 样本再多也只占固定的内存
+    server_ping: Arc<Mutex<Histogram>>,
+    launcher: Option<JoinHandle<()>>,
+    // 启动任务边启动边放进来 超时的时候能全部结束掉
+    bots: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    // 收到关闭时的截止时间 机器人离开最多等到这个时候
+    shutdown: watch::Sender<Option<tokio::time::Instant>>,
+}
+impl Swarm {
+    pub fn start(options: SwarmOptions) -> Self {
+        let states = Arc::new(Mutex::new(vec![BotState::Waiting; options.count]));
+        let timings = Arc::new(Mutex::new(vec![BotTiming::default(); options.count]));
+        let server_ping = Arc::new(Mutex::new(Histogram::default()));
+        let bots = Arc::new(Mutex::new(Vec::with_capacity(options.count)));
+        let (shutdown, shutdown_rx) = watch::channel(None);
+        let launcher = tokio::spawn(launch(
+            options,
+            states.clone(),
+            timings.clone(),
+            server_ping.clone(),
+            bots.clone(),
+            shutdown_rx,
+        ));
+        Self {
+            states,
+            timings,
+            server_ping,
+            launcher: Some(launcher),
+            bots,
+            shutdown,
+        }
+    }
+    pub fn states(&self) -> Vec<BotState> {
+        self.states.lock().unwrap().clone()
+    }
+    // 每种状态的机器人数量
+    pub fn summary(&self) -> BTreeMap<&'static str, usize> {
+        let mut summary = BTreeMap::new();
+        for state in self.states.lock().unwrap().iter() {
+            *summary.entry(state.label()).or_insert(0) += 1;
+        }
+        summary
+    }
+    // 所有机器人已经回复的心跳总数
+    pub fn heartbeats(&self) -> u64 {
+        self.timings.lock().unwrap().iter().map(|t| t.heartbeats).sum()
+    }
+    pub fn report(&self) -> LoadReport {
+        let server_ping = self.server_ping.lock().unwrap().clone();
+        LoadReport::from_timings(&self.timings.lock().unwrap(), &server_ping)
+    }
+    // 等所有机器人都断开
+    pub async fn wait(&mut self) {
+        if let Some(launcher) = self.launcher.as_mut() {
+            let _ = launcher.await;
+            self.launcher = None;
+        }
+        loop {
+            let bot = self.bots.lock().unwrap().pop();
+            let Some(bot) = bot else { break };
+            let _ = bot.await;
+        }
+    }
+    // 停止启动新的机器人 让所有机器人发离开包后断开
+    // 超过deadline还没断完的机器人直接结束掉 这时返回false
+    pub async fn shutdown(&mut self, deadline: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + deadline;
+        let _ = self.shutdown.send(Some(deadline));
+        if tokio::time::timeout_at(deadline, self.wait()).await.is_ok() {
+            return true;
+        }
+        self.abort();
+        false
+    }
+    fn abort(&mut self) {
+        if let Some(launcher) = self.launcher.take() {
+            launcher.abort();
+        }
+        for bot in self.bots.lock().unwrap().drain(..) {
+            bot.abort();
+        }
+    }
+}
+
+async fn launch(
+    options: SwarmOptions,
+    states: Arc<Mutex<Vec<BotState>>>,
+    timings: Arc<Mutex<Vec<BotTiming>>>,
+    server_ping: Arc<Mutex<Histogram>>,
+    bots: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    shutdown: watch::Receiver<Option<tokio::time::Instant>>,
+) {
+    let mut interval = tokio::time::interval(options.connect_interval);
+    for index in 0..options.count {
+        interval.tick().await;
+        if shutdown.borrow().is_some() {
+            break;
+        }
+        let mut builder = FakePlayerBuilder::new(&options.address)
+            .nickname(&options.nickname(index))
+            .event_capacity(SWARM_EVENT_CAPACITY);
+        if let Some(policy) = &options.reconnect {
+            builder = builder.reconnect(policy.clone());
+        }
+        if !options.local_addrs.is_empty() {
+            builder = builder.local_addr(options.local_addrs[index % options.local_addrs.len()]);
+        }
+        let bot = tokio::spawn(run_bot(
+            index,
+            builder,
+            states.clone(),
+            timings.clone(),
+            server_ping.clone(),
+            shutdown.clone(),
+        ));
+        bots.lock().unwrap().push(bot);
+    }
+}
+
+async fn run_bot(
+    index: usize,
+    builder: FakePlayerBuilder,
+    states: Arc<Mutex<Vec<BotState>>>,
+    timings: Arc<Mutex<Vec<BotTiming>>>,
+    server_ping: Arc<Mutex<Histogram>>,
+    mut shutdown: watch::Receiver<Option<tokio::time::Instant>>,
+) {
+    let set_state = |state: BotState| states.lock().unwrap()[index] = state;
+    let update_timing = |f: &dyn Fn(&mut BotTiming)| f(&mut timings.lock().unwrap()[index]);
+    set_state(BotState::Connecting);
+    // 每次连接重新计时 重连时从退避结束开始算
+    let mut connect_started = Instant::now();
+    // 连接的状态机比较大 放到堆上 连上以后就释放了
+    // 连接超时比关闭的截止时间长 还在连接的机器人收到关闭就不连了
+    let connect = Box::pin(builder.connect());
+    let result = tokio::select! {
+        // 错误类型不是Send 先转成字符串
+        result = connect => result.map_err(|e| e.to_string()),
+        Ok(()) = shutdown.changed() => {
+            set_state(BotState::Disconnected(DisconnectReason::Left));
+            return;
+        }
+    };
+    let mut player = match result {
+        Ok(player) => player,
+        Err(e) => {
+            update_timing(&|t| t.failure = Some("connect"));
+            set_state(BotState::Failed(e));
+            return;
+        }
+    };
+    set_state(BotState::Connected);
+    let mut registered_at: Option<Instant> = None;
+    let mut heartbeat_seen = false;
+    // 心跳是服务器发起的 客户端测不到往返 用服务器在队伍列表里报的延迟代替
+    // 每次心跳记一个最新的值 样本数不受队伍列表更新频率影响
+    let mut server_ping_ms: Option<Duration> = None;
+    loop {
+        let event = tokio::select! {
+            event = player.next_event() => match event {
+                Some(event) => event,
+                None => return,
+            },
+            Ok(()) = shutdown.changed() => {
+                let deadline = shutdown.borrow_and_update().unwrap_or_else(tokio::time::Instant::now);
+                match tokio::time::timeout_at(deadline, player.disconnect()).await {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => log::warn!("机器人{}断开失败:{}", index + 1, e),
+                    Err(_) => log::warn!("机器人{}没有按时断开", index + 1),
+                }
+                set_state(BotState::Disconnected(DisconnectReason::Left));
+                return;
+            }
+        };
+        match event {
+            ClientEvent::Registered => {
+                let now = Instant::now();
+                registered_at = Some(now);
+                update_timing(&|t| t.handshake = Some(now - connect_started));
+            }
+            ClientEvent::Heartbeat => {
+                update_timing(&|t| t.heartbeats += 1);
+                if let Some(ping) = server_ping_ms {
+                    server_ping.lock().unwrap().record(ping);
+                }
+                if let (false, Some(registered_at)) = (heartbeat_seen, registered_at) {
+                    heartbeat_seen = true;
+                    let elapsed = registered_at.elapsed();
+                    update_timing(&|t| t.first_heartbeat = Some(elapsed));
+                }
+            }
+            ClientEvent::RosterUpdated(list) => {
+                if let Some(me) = list.me() {
+                    server_ping_ms = Some(Duration::from_millis(me.ping.max(0) as u64));
+                }
+            }
+            ClientEvent::GameStarted => set_state(BotState::InGame),
+            ClientEvent::Reconnecting { delay, .. } => {
+                connect_started = Instant::now() + delay;
+                registered_at = None;
+                heartbeat_seen = false;
+                server_ping_ms = None;
+                set_state(BotState::Connecting);
+            }
+            ClientEvent::Reconnected => set_state(BotState::Connected),
+            ClientEvent::Disconnected { reason } => {
+                let failure = failure_kind(&reason);
+                update_timing(&|t| t.failure = failure);
+                set_state(BotState::Disconnected(reason));
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_finishes_within_the_deadline() {
+        // 接受连接但从来不回复 机器人一直停在握手
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut options = SwarmOptions::new(&listener.local_addr().unwrap().to_string(), 3);
+        options.connect_interval = Duration::from_millis(1);
+        let mut swarm = Swarm::start(options);
+        let server = tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = Instant::now();
+        swarm.shutdown(Duration::from_secs(1)).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(swarm.states().iter().all(|state| !matches!(state, BotState::Waiting)));
+        server.abort();
+    }
+}
@@ -0,0 +1,678 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use crate::client::PlayerOptions;
+use crate::analytics::DEFAULT_TICKS_PER_SECOND;
+use crate::network::ToBytes;
+use crate::packet::PacketWriter;
+use crate::protocol::heart::PACKET_HEART_BEAT;
+use crate::protocol::register_connection::RegisterConnectionPacket;
+use crate::capture::{Capture, CaptureEntry};
+
+// 内存连接每个方向的缓冲区大小
+pub const MEMORY_BUFFER: usize = 64 * 1024;
+// 代理回复头最长这么多 超过就当作代理出错
+const MAX_PROXY_RESPONSE: usize = 8 * 1024;
+// 按实时重放抓包时补发心跳的间隔
+pub const RECORDED_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransportInfo {
+    // tcp socks5 http-connect memory recorded
+    pub kind: &'static str,
+    pub peer: String,
+}
+impl fmt::Display for TransportInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.peer)
+    }
+}
+
+// 客户端收发字节用的连接 会被拆成读写两半 读的一半给会话 写的一半给写任务
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    fn info(&self) -> TransportInfo;
+}
+
+pub type ConnectFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+// 第一次连接和每次重连时调用 建立一个新的连接
+pub trait Connector: Send + Sync + 'static {
+    type Transport: Transport;
+    fn connect<'a>(&'a self, address: &'a str, options: &'a PlayerOptions) -> ConnectFuture<'a, Self::Transport>;
+}
+
+impl Transport for TcpStream {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            kind: "tcp",
+            peer: self.peer_addr().map(|a| a.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+impl Transport for DuplexStream {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            kind: "memory",
+            peer: String::new(),
+        }
+    }
+}
+
+// 给底层的流带上连接信息 代理和抓包用
+pub struct Labeled<S> {
+    inner: S,
+    info: TransportInfo,
+}
+impl<S> Labeled<S> {
+    pub fn new(inner: S, info: TransportInfo) -> Self {
+        Self { inner, info }
+    }
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+impl<S: AsyncRead + Unpin> AsyncRead for Labeled<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+impl<S: AsyncWrite + Unpin> AsyncWrite for Labeled<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+impl<S: AsyncRead + AsyncWrite + Send + Unpin + 'static> Transport for Labeled<S> {
+    fn info(&self) -> TransportInfo {
+        self.info.clone()
+    }
+}
+
+// 直连 设置了local_addr时绑定这个本地地址
+#[derive(Debug, Clone, Default)]
+pub struct TcpConnector;
+impl Connector for TcpConnector {
+    type Transport = TcpStream;
+    fn connect<'a>(&'a self, address: &'a str, options: &'a PlayerOptions) -> ConnectFuture<'a, TcpStream> {
+        Box::pin(tcp_connect(address, options.local_addr))
+    }
+}
+
+async fn tcp_connect(address: &str, local_addr: Option<IpAddr>) -> io::Result<TcpStream> {
+    match local_addr {
+        Some(local_addr) => {
+            let remote = lookup_host(address)
+                .await?
+                .find(|addr| addr.is_ipv4() == local_addr.is_ipv4())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "找不到和本地地址同类型的服务器地址"))?;
+            let socket = if local_addr.is_ipv4() {
+                TcpSocket::new_v4()?
+            } else {
+                TcpSocket::new_v6()?
+            };
+            socket.bind(SocketAddr::new(local_addr, 0))?;
+            socket.connect(remote).await
+        }
+        None => TcpStream::connect(address).await,
+    }
+}
+
+// 通过SOCKS5代理连接 服务器地址交给代理解析
+#[derive(Debug, Clone)]
+pub struct Socks5Connector {
+    proxy: String,
+    auth: Option<(String, String)>,
+}
+impl Socks5Connector {
+    pub fn new(proxy: &str) -> Self {
+        Self {
+            proxy: proxy.to_string(),
+            auth: None,
+        }
+    }
+    pub fn auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+}
+impl Connector for Socks5Connector {
+    type Transport = Labeled<TcpStream>;
+    fn connect<'a>(&'a self, address: &'a str, options: &'a PlayerOptions) -> ConnectFuture<'a, Self::Transport> {
+        Box::pin(async move {
+            let mut stream = tcp_connect(&self.proxy, options.local_addr).await?;
+            socks5_handshake(&mut stream, address, self.auth.as_ref()).await?;
+            Ok(Labeled::new(
+                stream,
+                TransportInfo {
+                    kind: "socks5",
+                    peer: format!("{} via {}", address, self.proxy),
+                },
+            ))
+        })
+    }
+}
+
+async fn socks5_handshake<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, address: &str, auth: Option<&(String, String)>) -> io::Result<()> {
+    let (host, port) = split_host_port(address)?;
+    // 0 不认证 2 用户名密码
+    let methods: &[u8] = if auth.is_some() { &[0, 2] } else { &[0] };
+    let mut hello = vec![5, methods.len() as u8];
+    hello.extend_from_slice(methods);
+    stream.write_all(&hello).await?;
+    let mut chosen = [0u8; 2];
+    stream.read_exact(&mut chosen).await?;
+    if chosen[0] != 5 {
+        return Err(proxy_error("不是SOCKS5代理".to_string()));
+    }
+    match chosen[1] {
+        0 => {}
+        2 => {
+            let (username, password) = auth.ok_or_else(|| proxy_error("代理要求用户名和密码".to_string()))?;
+            let mut request = vec![1];
+            for field in [username, password] {
+                let len = u8::try_from(field.len()).map_err(|_| proxy_error("用户名或密码太长".to_string()))?;
+                request.push(len);
+                request.extend_from_slice(field.as_bytes());
+            }
+            stream.write_all(&request).await?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[1] != 0 {
+                return Err(proxy_error("代理用户名或密码错误".to_string()));
+            }
+        }
+        _ => return Err(proxy_error("代理不支持我们的认证方式".to_string())),
+    }
+
+    // 连接命令 地址类型 1 IPv4 3 域名 4 IPv6
+    let mut request = vec![5, 1, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| proxy_error("域名太长".to_string()))?;
+            request.push(3);
+            request.push(len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head).await?;
+    if head[1] != 0 {
+        return Err(proxy_error(format!("代理连接失败:{}", socks5_reply(head[1]))));
+    }
+    // 后面是代理绑定的地址和端口 用不到 跳过
+    let address_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(proxy_error("代理回复的地址类型未知".to_string())),
+    };
+    let mut rest = vec![0u8; address_len + 2];
+    stream.read_exact(&mut rest).await?;
+    Ok(())
+}
+
+fn socks5_reply(code: u8) -> &'static str {
+    match code {
+        1 => "代理服务器错误",
+        2 => "代理规则不允许",
+        3 => "网络不可达",
+        4 => "主机不可达",
+        5 => "连接被拒绝",
+        6 => "TTL过期",
+        7 => "不支持的命令",
+        8 => "不支持的地址类型",
+        _ => "未知错误",
+    }
+}
+
+// 通过HTTP代理的CONNECT方法建立隧道
+#[derive(Debug, Clone)]
+pub struct HttpConnectConnector {
+    proxy: String,
+    auth: Option<(String, String)>,
+}
+impl HttpConnectConnector {
+    pub fn new(proxy: &str) -> Self {
+        Self {
+            proxy: proxy.to_string(),
+            auth: None,
+        }
+    }
+    pub fn auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+}
+impl Connector for HttpConnectConnector {
+    type Transport = Labeled<TcpStream>;
+    fn connect<'a>(&'a self, address: &'a str, options: &'a PlayerOptions) -> ConnectFuture<'a, Self::Transport> {
+        Box::pin(async move {
+            let mut stream = tcp_connect(&self.proxy, options.local_addr).await?;
+            http_connect(&mut stream, address, self.auth.as_ref()).await?;
+            Ok(Labeled::new(
+                stream,
+                TransportInfo {
+                    kind: "http-connect",
+                    peer: format!("{} via {}", address, self.proxy),
+                },
+            ))
+        })
+    }
+}
+
+async fn http_connect<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, address: &str, auth: Option<&(String, String)>) -> io::Result<()> {
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", address);
+    if let Some((username, password)) = auth {
+        let credentials = base64(format!("{}:{}", username, password).as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // 一个字节一个字节读 不能把隧道里服务器发来的数据读掉
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_PROXY_RESPONSE {
+            return Err(proxy_error("代理回复太长".to_string()));
+        }
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).await?;
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(proxy_error(format!("代理拒绝连接:{}", status_line)));
+    }
+    Ok(())
+}
+
+// 内存里的连接 测试时用MemoryListener当服务器
+#[derive(Debug, Clone)]
+pub struct MemoryConnector {
+    incoming: mpsc::UnboundedSender<DuplexStream>,
+}
+pub struct MemoryListener {
+    incoming: mpsc::UnboundedReceiver<DuplexStream>,
+}
+impl MemoryListener {
+    // 每次客户端连接或重连都会得到新的服务器端 所有MemoryConnector都丢掉后返回None
+    pub async fn accept(&mut self) -> Option<DuplexStream> {
+        self.incoming.recv().await
+    }
+}
+pub fn memory() -> (MemoryConnector, MemoryListener) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (MemoryConnector { incoming: tx }, MemoryListener { incoming: rx })
+}
+impl Connector for MemoryConnector {
+    type Transport = DuplexStream;
+    fn connect<'a>(&'a self, _address: &'a str, _options: &'a PlayerOptions) -> ConnectFuture<'a, DuplexStream> {
+        Box::pin(async move {
+            let (client, server) = tokio::io::duplex(MEMORY_BUFFER);
+            self.incoming
+                .send(server)
+                .map_err(|_| io::Error::new(io::ErrorKind::ConnectionRefused, "内存服务器已经关闭"))?;
+            Ok(client)
+        })
+    }
+}
+
+// 把CaptureRecorder录下的文件当作服务器发来的数据重放 客户端发出的数据全部丢掉
+// 抓包里没有161和心跳 开始时先补一个161让客户端完成握手 按实时重放时再定时补心跳
+#[derive(Debug, Clone)]
+pub struct RecordedConnector {
+    path: PathBuf,
+    realtime: bool,
+}
+impl RecordedConnector {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            realtime: false,
+        }
+    }
+    // 按tick的间隔发 默认一次全部发完
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+}
+impl Connector for RecordedConnector {
+    type Transport = Labeled<DuplexStream>;
+    fn connect<'a>(&'a self, _address: &'a str, _options: &'a PlayerOptions) -> ConnectFuture<'a, Self::Transport> {
+        Box::pin(async move {
+            let data = tokio::fs::read(&self.path).await?;
+            let frames = read_capture(data)?;
+            let (client, server) = tokio::io::duplex(MEMORY_BUFFER);
+            tokio::spawn(play_capture(server, frames, self.realtime));
+            Ok(Labeled::new(
+                client,
+                TransportInfo {
+                    kind: "recorded",
+                    peer: self.path.display().to_string(),
+                },
+            ))
+        })
+    }
+}
+
+// 开局的帧马上发 之后的tick按和第一个tick的距离换算成毫秒 自己发出的命令不重放
+fn read_capture(data: Vec<u8>) -> io::Result<Vec<(u64, Bytes)>> {
+    let capture = Capture::parse(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let mut frames: Vec<(u64, Bytes)> = capture.setup.into_iter().map(|frame| (0, frame)).collect();
+    let mut first_tick = None;
+    for entry in capture.entries {
+        if let CaptureEntry::Tick(tick, frame) = entry {
+            let first = *first_tick.get_or_insert(tick);
+            let millis = (tick - first).max(0) as f64 * 1000.0 / DEFAULT_TICKS_PER_SECOND;
+            frames.push((millis as u64, frame));
+        }
+    }
+    Ok(frames)
+}
+
+async fn play_capture(server: DuplexStream, frames: Vec<(u64, Bytes)>, realtime: bool) {
+    let (mut reader, mut writer) = tokio::io::split(server);
+    // 不读的话缓冲区满了客户端会写不出去
+    tokio::spawn(async move {
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = reader.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    });
+    let mut register = RegisterConnectionPacket::new();
+    register.network_server_id = Uuid::new_v4().to_string();
+    let Ok(register) = register.to_bytes() else { return };
+    if writer.write_all(&register).await.is_err() {
+        return;
+    }
+    let started = tokio::time::Instant::now();
+    let mut heartbeats = tokio::time::interval_at(started + RECORDED_HEARTBEAT_INTERVAL, RECORDED_HEARTBEAT_INTERVAL);
+    let mut ping: i64 = 0;
+    for (millis, frame) in frames {
+        if realtime {
+            let due = tokio::time::sleep_until(started + Duration::from_millis(millis));
+            tokio::pin!(due);
+            loop {
+                tokio::select! {
+                    _ = &mut due => break,
+                    _ = heartbeats.tick() => {
+                        ping += 1;
+                        let Ok(heart) = heart_frame(ping) else { return };
+                        if writer.write_all(&heart).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+        if writer.write_all(&frame).await.is_err() {
+            return;
+        }
+    }
+    // 放完后客户端会读到连接关闭
+    let _ = writer.shutdown().await;
+}
+
+fn heart_frame(ping: i64) -> Result<Bytes, crate::error::PacketError> {
+    let mut out = PacketWriter::with_capacity(17);
+    let frame = out.begin_frame(PACKET_HEART_BEAT)?;
+    out.write_i64(ping)?;
+    out.write_byte(0)?;
+    out.finish_frame(frame)?;
+    Ok(out.payload.freeze())
+}
+
+// 主机和端口 IPv6地址要用方括号括起来
+fn split_host_port(address: &str) -> io::Result<(&str, u16)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("服务器地址格式错误:{}", address));
+    let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok((host, port))
+}
+
+fn proxy_error(message: String) -> io::Error {
+    io::Error::other(message)
+}
+
+fn base64(input: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(TABLE[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::FakePlayerBuilder;
+    use crate::event::ClientEvent;
+    use crate::packet::frame;
+    use crate::protocol::player_info::PACKET_PLAYER_INFO;
+    use crate::protocol::preregister_connection::PACKET_PREREGISTER_CONNECTION;
+    use crate::protocol::start_game::PACKET_START_GAME;
+    use crate::protocol::tick::PACKET_TICK;
+    use crate::capture::CaptureRecorder;
+
+    async fn read_frame_type(stream: &mut DuplexStream) -> i32 {
+        let len = stream.read_i32().await.unwrap();
+        let packet_type = stream.read_i32().await.unwrap();
+        let mut body = vec![0u8; len as usize];
+        stream.read_exact(&mut body).await.unwrap();
+        packet_type
+    }
+
+    async fn next_matching(player: &mut crate::client::FakePlayer, wanted: &ClientEvent) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match player.next_event().await {
+                    Some(event) if &event == wanted => return,
+                    Some(_) => {}
+                    None => panic!("没等到{:?}连接就结束了", wanted),
+                }
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"user:pass"), "dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn splits_host_and_port() {
+        assert_eq!(split_host_port("127.0.0.1:5123").unwrap(), ("127.0.0.1", 5123));
+        assert_eq!(split_host_port("[::1]:5123").unwrap(), ("::1", 5123));
+        assert_eq!(split_host_port("example.com:80").unwrap(), ("example.com", 80));
+        assert!(split_host_port("nope").is_err());
+        assert!(split_host_port("host:notaport").is_err());
+    }
+
+    #[tokio::test]
+    async fn socks5_handshake_with_password() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        let proxy = tokio::spawn(async move {
+            let mut hello = [0u8; 4];
+            proxy.read_exact(&mut hello).await.unwrap();
+            assert_eq!(hello, [5, 2, 0, 2]);
+            proxy.write_all(&[5, 2]).await.unwrap();
+            let mut login = [0u8; 11];
+            proxy.read_exact(&mut login).await.unwrap();
+            assert_eq!(&login, b"\x01\x04user\x04pass");
+            proxy.write_all(&[1, 0]).await.unwrap();
+            let mut request = vec![0u8; 5 + "example.com".len() + 2];
+            proxy.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[..5], [5, 1, 0, 3, 11]);
+            assert_eq!(&request[5..16], b"example.com");
+            assert_eq!(request[16..], 5123u16.to_be_bytes());
+            proxy.write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x14, 0x03]).await.unwrap();
+            // 握手之后就是隧道
+            proxy.write_all(b"tunnel").await.unwrap();
+            proxy
+        });
+        let auth = ("user".to_string(), "pass".to_string());
+        socks5_handshake(&mut client, "example.com:5123", Some(&auth)).await.unwrap();
+        let mut data = [0u8; 6];
+        client.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"tunnel");
+        let _proxy = proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn socks5_reports_the_failure_reply() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        let proxy = tokio::spawn(async move {
+            let mut hello = [0u8; 3];
+            proxy.read_exact(&mut hello).await.unwrap();
+            proxy.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0u8; 10];
+            proxy.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[3], 1);
+            proxy.write_all(&[5, 5, 0, 1]).await.unwrap();
+            proxy
+        });
+        let error = socks5_handshake(&mut client, "127.0.0.1:5123", None).await.unwrap_err();
+        assert!(error.to_string().contains("连接被拒绝"), "{}", error);
+        let _proxy = proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_connect_with_password() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        let proxy = tokio::spawn(async move {
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(proxy.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+            assert!(request.starts_with("CONNECT example.com:5123 HTTP/1.1\r\n"), "{}", request);
+            assert!(request.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"), "{}", request);
+            // 回复和隧道里的数据一起到 握手不能把后面的读掉
+            proxy
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\ntunnel")
+                .await
+                .unwrap();
+            proxy
+        });
+        let auth = ("user".to_string(), "pass".to_string());
+        http_connect(&mut client, "example.com:5123", Some(&auth)).await.unwrap();
+        let mut data = [0u8; 6];
+        client.read_exact(&mut data).await.unwrap();
+        assert_eq!(&data, b"tunnel");
+        let _proxy = proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn http_connect_rejected() {
+        let (mut client, mut proxy) = tokio::io::duplex(1024);
+        let proxy = tokio::spawn(async move {
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(proxy.read_u8().await.unwrap());
+            }
+            proxy
+                .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .await
+                .unwrap();
+            proxy
+        });
+        let error = http_connect(&mut client, "example.com:5123", None).await.unwrap_err();
+        assert!(error.to_string().contains("407"), "{}", error);
+        let _proxy = proxy.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn registers_over_memory() {
+        let (connector, mut listener) = memory();
+        let server = tokio::spawn(async move {
+            let mut stream = listener.accept().await.unwrap();
+            assert_eq!(read_frame_type(&mut stream).await, PACKET_PREREGISTER_CONNECTION);
+            let mut register = RegisterConnectionPacket::new();
+            register.network_server_id = Uuid::new_v4().to_string();
+            stream.write_all(&register.to_bytes().unwrap()).await.unwrap();
+            assert_eq!(read_frame_type(&mut stream).await, PACKET_PLAYER_INFO);
+            stream
+        });
+        let mut player = FakePlayerBuilder::new("memory")
+            .connector(connector)
+            .connect()
+            .await
+            .unwrap();
+        next_matching(&mut player, &ClientEvent::Registered).await;
+        let _stream = server.await.unwrap();
+        player.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_a_recording_after_a_synthesized_161() {
+        let path = std::env::temp_dir().join(format!("rwnew-recorded-{}.capture", std::process::id()));
+        let start = Bytes::from(frame(PACKET_START_GAME, |_| {}));
+        let tick = frame(PACKET_TICK, |out| {
+            out.write_i32(1).unwrap();
+            out.write_i32(0).unwrap();
+        });
+        let mut recorder = CaptureRecorder::create(&path, &[start]).await.unwrap();
+        recorder.record(&CaptureEntry::Tick(1, tick.into())).await.unwrap();
+        recorder.finish().await.unwrap();
+
+        let mut player = FakePlayerBuilder::new("recorded")
+            .connector(RecordedConnector::new(&path))
+            .connect()
+            .await
+            .unwrap();
+        next_matching(&mut player, &ClientEvent::Registered).await;
+        next_matching(&mut player, &ClientEvent::GameStarted).await;
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}